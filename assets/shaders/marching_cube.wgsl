@@ -1,5 +1,123 @@
+// -- GPU twin of src/terrain_engine/chunk/mesher.rs, one invocation per cell --
 
-@compute @workgroup_size(8, 8, 1)
-fn init(@builtin(global_invocation_id) invocation_id: vec3<u32>, @builtin(num_workgroups) num_workgroups: vec3<u32>) {
-    
+struct Params {
+    // -- Size of the density grid
+    dims: vec3<u32>,
+    iso_level: f32,
+
+    // -- Cells to mesh, min inclusive and max exclusive
+    min: vec3<u32>,
+    max_triangles: u32,
+    max: vec3<u32>,
+};
+
+struct Counter {
+    triangles: atomic<u32>,
+};
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var<storage, read> densities: array<f32>;
+
+// -- TRIANGULATION_TABLE flattened to 256 * 16 entries
+@group(0) @binding(2)
+var<storage, read> triangulation: array<i32>;
+
+// -- 6 floats per vertex, position then normal
+@group(0) @binding(3)
+var<storage, read_write> vertices: array<f32>;
+
+@group(0) @binding(4)
+var<storage, read_write> indices: array<u32>;
+
+@group(0) @binding(5)
+var<storage, read_write> counter: Counter;
+
+fn sample_index(p: vec3<u32>) -> u32 {
+    return p.x + p.y * params.dims.x + p.z * params.dims.x * params.dims.y;
+}
+
+fn sample(p: vec3<u32>) -> f32 {
+    return densities[sample_index(p)];
+}
+
+fn gradient(p: vec3<u32>) -> vec3<f32> {
+    let lo = max(p, vec3<u32>(1u)) - vec3<u32>(1u);
+    let hi = min(p + vec3<u32>(1u), params.dims - vec3<u32>(1u));
+    let span = vec3<f32>(max(hi - lo, vec3<u32>(1u)));
+
+    return vec3<f32>(
+        sample(vec3<u32>(hi.x, p.y, p.z)) - sample(vec3<u32>(lo.x, p.y, p.z)),
+        sample(vec3<u32>(p.x, hi.y, p.z)) - sample(vec3<u32>(p.x, lo.y, p.z)),
+        sample(vec3<u32>(p.x, p.y, hi.z)) - sample(vec3<u32>(p.x, p.y, lo.z)),
+    ) / span;
+}
+
+fn interpolation_factor(a: f32, b: f32) -> f32 {
+    if (abs(b - a) < 1.1920929e-7) { return 0.5; }
+    return clamp((params.iso_level - a) / (b - a), 0.0, 1.0);
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn march(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let cell = params.min + invocation_id;
+    if (any(cell >= params.max)) { return; }
+
+    // -- Same corner order as CORNER_OFFSETS on the CPU
+    var offsets = array<vec3<u32>, 8>(
+        vec3<u32>(0u, 0u, 0u),
+        vec3<u32>(1u, 0u, 0u),
+        vec3<u32>(1u, 0u, 1u),
+        vec3<u32>(0u, 0u, 1u),
+        vec3<u32>(0u, 1u, 0u),
+        vec3<u32>(1u, 1u, 0u),
+        vec3<u32>(1u, 1u, 1u),
+        vec3<u32>(0u, 1u, 1u),
+    );
+
+    // -- CORNER_INDEX, the two corners at the ends of each edge
+    var edges = array<vec2<u32>, 12>(
+        vec2<u32>(0u, 1u), vec2<u32>(1u, 2u), vec2<u32>(2u, 3u), vec2<u32>(3u, 0u),
+        vec2<u32>(4u, 5u), vec2<u32>(5u, 6u), vec2<u32>(6u, 7u), vec2<u32>(7u, 4u),
+        vec2<u32>(0u, 4u), vec2<u32>(1u, 5u), vec2<u32>(2u, 6u), vec2<u32>(3u, 7u),
+    );
+
+    var values: array<f32, 8>;
+    var cube_index = 0u;
+
+    for (var i = 0u; i < 8u; i = i + 1u) {
+        values[i] = sample(cell + offsets[i]);
+        if (values[i] > params.iso_level) { cube_index = cube_index | (1u << i); }
+    }
+
+    for (var i = 0u; i < 16u; i = i + 3u) {
+        if (triangulation[cube_index * 16u + i] < 0) { break; }
+
+        let triangle = atomicAdd(&counter.triangles, 1u);
+        if (triangle >= params.max_triangles) { return; }
+
+        for (var j = 0u; j < 3u; j = j + 1u) {
+            let edge = edges[u32(triangulation[cube_index * 16u + i + j])];
+            let a = cell + offsets[edge.x];
+            let b = cell + offsets[edge.y];
+            let t = interpolation_factor(values[edge.x], values[edge.y]);
+
+            let position = mix(vec3<f32>(a), vec3<f32>(b), t) - vec3<f32>(params.min);
+            let slope = -mix(gradient(a), gradient(b), t);
+            var normal = vec3<f32>(0.0);
+            if (length(slope) > 0.0) { normal = normalize(slope); }
+
+            let vertex = triangle * 3u + j;
+            vertices[vertex * 6u + 0u] = position.x;
+            vertices[vertex * 6u + 1u] = position.y;
+            vertices[vertex * 6u + 2u] = position.z;
+            vertices[vertex * 6u + 3u] = normal.x;
+            vertices[vertex * 6u + 4u] = normal.y;
+            vertices[vertex * 6u + 5u] = normal.z;
+
+            indices[vertex] = vertex;
+        }
+    }
 }
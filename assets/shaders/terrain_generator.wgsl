@@ -0,0 +1,135 @@
+// -- GPU twin of src/terrain_engine/noise.rs and generator.rs, the two must
+// produce the same densities for the same seed --
+
+struct Params {
+    // -- World position of the first sample and the size of the grid
+    origin: vec3<i32>,
+    seed: u32,
+    dims: vec3<u32>,
+    base_height: f32,
+
+    // -- Frequency, amplitude, lacunarity and persistence of each noise layer
+    continents: vec4<f32>,
+    hills: vec4<f32>,
+    caves: vec4<f32>,
+
+    // -- Octaves of the continents, hills and caves
+    octaves: vec3<u32>,
+    cave_threshold: f32,
+
+    grass_depth: f32,
+    dirt_depth: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var<storage, read_write> densities: array<f32>;
+
+@group(0) @binding(2)
+var<storage, read_write> materials: array<u32>;
+
+// -- Keep in sync with terrain_engine::material --
+let AIR: u32 = 0u;
+let STONE: u32 = 1u;
+let DIRT: u32 = 2u;
+let GRASS: u32 = 3u;
+
+fn hash(p: vec3<i32>, seed: u32) -> u32 {
+    var h = seed
+        ^ (bitcast<u32>(p.x) * 0x8da6b343u)
+        ^ (bitcast<u32>(p.y) * 0xd8163841u)
+        ^ (bitcast<u32>(p.z) * 0xcb1ab31fu);
+
+    h = (h ^ (h >> 16u)) * 0x7feb352du;
+    h = (h ^ (h >> 15u)) * 0x846ca68bu;
+    return h ^ (h >> 16u);
+}
+
+fn corner(cell: vec3<i32>, offset: vec3<i32>, local: vec3<f32>, seed: u32) -> f32 {
+    var gradients = array<vec3<f32>, 12>(
+        vec3<f32>(1.0, 1.0, 0.0), vec3<f32>(-1.0, 1.0, 0.0), vec3<f32>(1.0, -1.0, 0.0), vec3<f32>(-1.0, -1.0, 0.0),
+        vec3<f32>(1.0, 0.0, 1.0), vec3<f32>(-1.0, 0.0, 1.0), vec3<f32>(1.0, 0.0, -1.0), vec3<f32>(-1.0, 0.0, -1.0),
+        vec3<f32>(0.0, 1.0, 1.0), vec3<f32>(0.0, -1.0, 1.0), vec3<f32>(0.0, 1.0, -1.0), vec3<f32>(0.0, -1.0, -1.0),
+    );
+
+    let gradient = gradients[hash(cell + offset, seed) % 12u];
+    return dot(gradient, local - vec3<f32>(offset));
+}
+
+fn fade(t: vec3<f32>) -> vec3<f32> {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+// -- Written out instead of mix(), to round the same way as the CPU --
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    return a + (b - a) * t;
+}
+
+fn gradient_noise(position: vec3<f32>, seed: u32) -> f32 {
+    let floored = floor(position);
+    let cell = vec3<i32>(floored);
+    let local = position - floored;
+    let t = fade(local);
+
+    let x00 = lerp(corner(cell, vec3<i32>(0, 0, 0), local, seed), corner(cell, vec3<i32>(1, 0, 0), local, seed), t.x);
+    let x10 = lerp(corner(cell, vec3<i32>(0, 1, 0), local, seed), corner(cell, vec3<i32>(1, 1, 0), local, seed), t.x);
+    let x01 = lerp(corner(cell, vec3<i32>(0, 0, 1), local, seed), corner(cell, vec3<i32>(1, 0, 1), local, seed), t.x);
+    let x11 = lerp(corner(cell, vec3<i32>(0, 1, 1), local, seed), corner(cell, vec3<i32>(1, 1, 1), local, seed), t.x);
+
+    let y0 = lerp(x00, x10, t.y);
+    let y1 = lerp(x01, x11, t.y);
+
+    return lerp(y0, y1, t.z);
+}
+
+fn sample_layer(layer: vec4<f32>, octaves: u32, position: vec3<f32>, seed: u32) -> f32 {
+    var frequency = layer.x;
+    var amplitude = 1.0;
+    var total = 0.0;
+    var range = 0.0;
+
+    for (var octave = 0u; octave < octaves; octave = octave + 1u) {
+        total = total + gradient_noise(position * frequency, seed + octave) * amplitude;
+        range = range + amplitude;
+
+        frequency = frequency * layer.z;
+        amplitude = amplitude * layer.w;
+    }
+
+    if (range == 0.0) { return 0.0; }
+    return total / range * layer.y;
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn generate(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (any(invocation_id >= params.dims)) { return; }
+
+    let world = params.origin + vec3<i32>(invocation_id);
+    let column = vec3<f32>(f32(world.x), 0.0, f32(world.z));
+
+    let height = params.base_height
+        + sample_layer(params.continents, params.octaves.x, column, params.seed)
+        + sample_layer(params.hills, params.octaves.y, column, params.seed + 100u);
+
+    let depth = height - f32(world.y);
+    let cave = sample_layer(params.caves, params.octaves.z, vec3<f32>(world), params.seed + 200u);
+    let density = min(depth, (params.cave_threshold - cave) * 8.0);
+
+    var material = STONE;
+    if (density <= 0.0) {
+        material = AIR;
+    } else if (depth < params.grass_depth) {
+        material = GRASS;
+    } else if (depth < params.dirt_depth) {
+        material = DIRT;
+    }
+
+    let index = invocation_id.x
+        + invocation_id.y * params.dims.x
+        + invocation_id.z * params.dims.x * params.dims.y;
+
+    densities[index] = density;
+    materials[index] = material;
+}
@@ -16,16 +16,23 @@ impl Rotation {
     }
 }
 
+// -- The main light, configured from the graphics settings
+#[derive(Component, Default)]
+pub struct Sun;
+
 // endregion: --Common Components--
 
 // region: --Character controller--
 
-#[derive(Default, Clone, Reflect)]
+#[derive(Default, Clone, Copy, PartialEq, Reflect)]
 #[reflect(name = "Camera Mode")]
 pub enum CameraMode {
     #[default]
     Orbit,
     FirstPerson,
+
+    // -- Detached from the player, for looking around the terrain
+    Spectator,
 }
 
 #[derive(Default, Reflect, Clone, Component)]
@@ -36,6 +43,10 @@ pub struct OrbitCamera {
     pub horizontal_angle: f32,
     pub vertical_angle: f32,
 
+    // -- Where the look input wants the angles to be, the angles above ease towards these
+    pub target_horizontal_angle: f32,
+    pub target_vertical_angle: f32,
+
     pub inverted_x: bool,   
     pub inverted_y: bool,
 
@@ -44,10 +55,38 @@ pub struct OrbitCamera {
 
     pub camera_step: u32,
     pub camera_step_distance: u32,
-    pub camera_step_lerp: f32,  
     pub camera_step_max: u32,
 
     pub camera_position: Vec3,  
+
+    // -- Seconds for the distance, angles and follow position to close half
+    // the gap to their targets, 0 snaps straight there
+    pub distance_half_life: f32,
+    pub angle_half_life: f32,
+    pub follow_half_life: f32,
+
+    // -- Smoothed player position the camera orbits, it snaps when the
+    // player gets further away than the snap distance (teleports, spawning)
+    pub follow_position: Vec3,
+    pub follow_snap_distance: f32,
+
+    // -- Where the eyes sit relative to the center of the player
+    pub first_person_offset: Vec3,
+
+    // -- 0 is fully orbit, 1 is fully first person, eased over time
+    pub first_person_blend: f32,
+    pub first_person_blend_speed: f32,
+
+    // -- Spring arm, the distance actually used once obstructions are accounted for
+    pub arm_length: f32,
+    pub arm_return_speed: f32,
+    pub collision_radius: f32,
+
+    // -- Spectator flight, speed in units per second, changed with the zoom keys
+    pub spectator_speed: f32,
+    pub spectator_speed_min: f32,
+    pub spectator_speed_max: f32,
+    pub noclip: bool,
 }
 
 impl OrbitCamera {
@@ -57,14 +96,30 @@ impl OrbitCamera {
             camera_distance: 0.0,
             horizontal_angle: 0.0,  
             vertical_angle: 0.0,
+            target_horizontal_angle: 0.0,
+            target_vertical_angle: 0.0,
             inverted_x: false,
             inverted_y: false,
             camera_mode: CameraMode::Orbit, 
             camera_step: 3,
             camera_step_distance: 5,
-            camera_step_lerp: 0.25,
             camera_step_max: 5,       
             camera_position: Vec3::default(),
+            distance_half_life: 0.08,
+            angle_half_life: 0.02,
+            follow_half_life: 0.03,
+            follow_position: Vec3::default(),
+            follow_snap_distance: 16.0,
+            first_person_offset: Vec3::new(0.0, 0.7, 0.0),
+            first_person_blend: 0.0,
+            first_person_blend_speed: 4.0,
+            arm_length: 0.0,
+            arm_return_speed: 8.0,
+            collision_radius: 0.25,
+            spectator_speed: 16.0,
+            spectator_speed_min: 2.0,
+            spectator_speed_max: 256.0,
+            noclip: true,
         }
     }
 }
@@ -74,27 +129,51 @@ impl OrbitCamera {
 #[reflect(name = "Player", Component)]    
 pub struct Player;
 
-// endregion: --Character controller--
+#[derive(Reflect, Clone, Component)]
+#[reflect(name = "Character Controller", Component)]
+pub struct CharacterController {
+    pub speed: f32,
+    pub acceleration: f32,
+    pub air_acceleration: f32,
+    pub jump_speed: f32,
+
+    // -- Steepest slope in degrees that still counts as ground
+    pub max_slope: f32,
 
+    // -- Tallest ledge that is walked up without jumping
+    pub step_height: f32,
 
-// region: --Inputs--  
+    // -- Seconds it takes to rise onto a ledge
+    pub step_time: f32,
 
-#[derive(Component)] 
-pub struct CameraInputs {
-    pub zoom_in: KeyCode,
-    pub zoom_out: KeyCode,
+    // -- Capsule shape, used for the ground and step checks
+    pub radius: f32,
+    pub half_height: f32,
+
+    pub grounded: bool,
+    pub ground_normal: Vec3,
 }
 
-impl CameraInputs {
-    pub fn default() -> Self {
+impl Default for CharacterController {
+    fn default() -> Self {
         Self {
-            zoom_in: KeyCode::X,
-            zoom_out: KeyCode::Z,
+            speed: 6.0,
+            acceleration: 40.0,
+            air_acceleration: 8.0,
+            jump_speed: 6.5,
+            max_slope: 50.0,
+            step_height: 0.6,
+            step_time: 0.1,
+            radius: 0.5,
+            half_height: 1.0,
+            grounded: false,
+            ground_normal: Vec3::Y,
         }
     }
 }
 
-// endregion: --Inputs--
+// endregion: --Character controller--
+
 
 pub enum Initialized {
     Loading,
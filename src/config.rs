@@ -0,0 +1,59 @@
+use bevy::log::error;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, path::PathBuf};
+
+// -- Everything we write lives in <user config dir>/PhysicalVoxel --
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("PhysicalVoxel")
+}
+
+// -- Saved worlds and other bulky data live in <user data dir>/PhysicalVoxel --
+pub fn data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("PhysicalVoxel")
+}
+
+pub fn config_path(name: &str) -> PathBuf {
+    config_dir().join(name)
+}
+
+/// Reads a RON file from the config dir. A file that is missing or fails to
+/// parse logs an error and gives the defaults, which get written on exit.
+pub fn load<T: DeserializeOwned + Default>(name: &str) -> T {
+    let path = config_path(name);
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            error!("Failed to read {}, using defaults: {}", path.display(), err);
+            return T::default();
+        }
+    };
+
+    match ron::from_str(&contents) {
+        Ok(value) => value,
+        Err(err) => {
+            error!("Failed to parse {}, using defaults: {}", path.display(), err);
+            T::default()
+        }
+    }
+}
+
+pub fn save<T: Serialize>(name: &str, value: &T) {
+    let path = config_path(name);
+
+    let contents = match ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default()) {
+        Ok(contents) => contents,
+        Err(err) => {
+            error!("Failed to serialize {}: {}", path.display(), err);
+            return;
+        }
+    };
+
+    if let Err(err) = fs::create_dir_all(config_dir()).and_then(|_| fs::write(&path, contents)) {
+        error!("Failed to write {}: {}", path.display(), err);
+    }
+}
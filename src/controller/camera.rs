@@ -1,59 +1,176 @@
-use bevy::{prelude::{With, Query, Camera, Transform, EventReader, ParamSet }, input::mouse::MouseMotion};
+use bevy::{prelude::{With, Query, Camera, Transform, ParamSet, Res, Visibility, Entity, Quat, Vec3 }, time::Time};
+use bevy_rapier3d::prelude::{Collider, RapierContext, QueryFilter};
 use crate::components::{OrbitCamera, CameraMode, Player};
+use super::input::{Action, ActionState};
 
 pub mod orbital_camera;
 pub mod camera_distance;
+pub mod fp_camera;
+pub mod spectator_camera;
 
 pub fn manager(
     mut query: ParamSet<(
         Query<(&mut Camera, &mut OrbitCamera, &mut Transform), With<OrbitCamera>>,
-        Query<&mut Transform, With<Player>>,    
+        Query<(Entity, &Transform, &mut Visibility), With<Player>>,    
     )>,
-    mut motion_evr: EventReader<MouseMotion>,
+    actions: Res<ActionState>,
+    rapier_context: Res<RapierContext>,
+    time: Res<Time>,
 ) {
     let mut player_transform = Transform::default();    
+    let mut player_entity = None;
+    let mut hide_player = false;
 
-    for transform in query.p1().iter_mut() {
+    for (entity, transform, _) in query.p1().iter_mut() {
         player_transform = *transform;
+        player_entity = Some(entity);
     }
 
     for (_, mut orbital_camera, mut transform) in query.p0().iter_mut() {
 
-        // -- Get the mouse position
-        for ev in motion_evr.iter() {
-            let x = invert(ev.delta.x / 100.0, orbital_camera.inverted_x);
-            let y = invert(ev.delta.y / 100.0, orbital_camera.inverted_y);
+        let delta = time.delta_seconds();
+
+        // -- Get the look input, mouse and right stick combined. Mouse deltas are
+        // a distance moved so they are not scaled by time, the stick already is
+        {
+            let x = invert(actions.look.x, orbital_camera.inverted_x);
+            let y = invert(actions.look.y, orbital_camera.inverted_y);
 
             // -- X should be able to loop an infinite amount of times.
-            orbital_camera.horizontal_angle = 
-                clamp_overflow(orbital_camera.horizontal_angle + x * orbital_camera.mouse_sensitivity, 0.0, 360.0);
+            orbital_camera.target_horizontal_angle =
+                (orbital_camera.target_horizontal_angle + x * orbital_camera.mouse_sensitivity).rem_euclid(360.0);
 
             // -- Y should be clamped to a minimum of -90.0 and a maximum of 90.0 due to the camera going upside down --
-            orbital_camera.vertical_angle = 
-                (orbital_camera.vertical_angle - y * orbital_camera.mouse_sensitivity).min(89.0).max(-89.0);
+            orbital_camera.target_vertical_angle =
+                (orbital_camera.target_vertical_angle - y * orbital_camera.mouse_sensitivity).min(89.0).max(-89.0);
+
+            // -- Ease the angles towards the targets, horizontally the short way around
+            let t = smoothing(orbital_camera.angle_half_life, delta);
+            let turn = shortest_angle(orbital_camera.horizontal_angle, orbital_camera.target_horizontal_angle);
+
+            orbital_camera.horizontal_angle = (orbital_camera.horizontal_angle + turn * t).rem_euclid(360.0);
+            orbital_camera.vertical_angle +=
+                (orbital_camera.target_vertical_angle - orbital_camera.vertical_angle) * t;
+        }
+
+        // -- Detach from the player, or snap straight back to it
+        if actions.just_pressed(Action::ToggleSpectator) {
+            orbital_camera.camera_mode = match orbital_camera.camera_mode {
+                CameraMode::Spectator if orbital_camera.camera_step == 0 => CameraMode::FirstPerson,
+                CameraMode::Spectator => CameraMode::Orbit,
+                _ => CameraMode::Spectator,
+            };
+
+            // -- Skip easing the spring arm and follow position back
+            orbital_camera.arm_length = orbital_camera.camera_distance;
+            orbital_camera.follow_position = player_transform.translation;
         }
 
-        match orbital_camera.camera_mode {
-            CameraMode::Orbit => {
-                orbital_camera::manager(
-                    &mut orbital_camera,
-                    &mut transform,
-                    player_transform
-                );
-            },
+        if orbital_camera.camera_mode == CameraMode::Spectator {
+            if actions.just_pressed(Action::ToggleNoclip) {
+                orbital_camera.noclip = !orbital_camera.noclip;
+            }
 
-            CameraMode::FirstPerson => {
+            let axis = |positive: Action, negative: Action| {
+                actions.pressed(positive) as i32 as f32 - actions.pressed(negative) as i32 as f32
+            };
 
-            },  
+            let movement = Vec3::new(
+                actions.movement.x,
+                axis(Action::FlyUp, Action::FlyDown),
+                actions.movement.y,
+            );
+
+            spectator_camera::manager(
+                &mut orbital_camera,
+                &mut transform,
+                movement,
+                delta,
+                &rapier_context,
+            );
+
+            continue;
         }
+
+        // -- Ease the followed point towards the player, unless it is too far behind
+        let follow_distance = orbital_camera.follow_position.distance(player_transform.translation);
+
+        orbital_camera.follow_position = if follow_distance > orbital_camera.follow_snap_distance {
+            player_transform.translation
+        } else {
+            let t = smoothing(orbital_camera.follow_half_life, delta);
+            orbital_camera.follow_position.lerp(player_transform.translation, t)
+        };
+
+        let follow_transform = Transform {
+            translation: orbital_camera.follow_position,
+            ..player_transform
+        };
+
+        // -- Ease towards the mode the camera is in
+        let target = match orbital_camera.camera_mode {
+            CameraMode::FirstPerson => 1.0,
+            _ => 0.0,
+        };
+
+        let step = orbital_camera.first_person_blend_speed * delta;
+        orbital_camera.first_person_blend +=
+            (target - orbital_camera.first_person_blend).clamp(-step, step);
+
+        // -- Sweep a ball from the player out to the camera, anything in the
+        // way shortens the arm
+        let mut filter = QueryFilter::default().exclude_sensors();
+        if let Some(entity) = player_entity { filter = filter.exclude_rigid_body(entity); }
+
+        let direction = orbital_camera::calculate_orbit(
+            orbital_camera.horizontal_angle,
+            orbital_camera.vertical_angle,
+            1.0,
+        );
+
+        let obstruction = rapier_context.cast_shape(
+            follow_transform.translation,
+            Quat::IDENTITY,
+            direction,
+            &Collider::ball(orbital_camera.collision_radius),
+            orbital_camera.camera_distance,
+            filter,
+        ).map(|(_, toi)| toi.toi);
+
+        orbital_camera::spring_arm(&mut orbital_camera, obstruction, delta);
+
+        // -- First person starts from the orbit position and blends it towards the eyes
+        orbital_camera::manager(
+            &mut orbital_camera,
+            &mut transform,
+            follow_transform
+        );
+
+        fp_camera::manager(
+            &mut orbital_camera,
+            &mut transform,
+            follow_transform
+        );
+
+        // -- Dont render the inside of the players own capsule
+        hide_player |= orbital_camera.first_person_blend > 0.5;
+    }
+
+    for (_, _, mut visibility) in query.p1().iter_mut() {
+        visibility.is_visible = !hide_player;
     }
 }
 
-// -- EG: Value = 400, max = 360, min = 0, result = 0.0 -- 
-fn clamp_overflow(value: f32, min: f32, max: f32) -> f32 {
-    if value > max { return min; }
-    if value < min { return max; }
-    return value;
+// -- Fraction of the way to move towards a target this frame, so half the
+// gap is closed every `half_life` seconds whatever the frame rate --
+pub fn smoothing(half_life: f32, delta: f32) -> f32 {
+    if half_life <= 0.0 { return 1.0; }
+    1.0 - 0.5_f32.powf(delta / half_life)
+}
+
+// -- EG: from = 350, to = 10, result = 20.0 --
+fn shortest_angle(from: f32, to: f32) -> f32 {
+    (to - from + 180.0).rem_euclid(360.0) - 180.0
 }
 
 fn invert(x: f32, trig: bool) -> f32 { 
@@ -1,14 +1,30 @@
-use bevy::{prelude::{KeyCode, Res, With, Query}, input::Input};
-use crate::components::{OrbitCamera, CameraMode, CameraInputs};
+use bevy::{prelude::{Res, With, Query}, time::Time};
+use crate::components::{OrbitCamera, CameraMode};
+use crate::controller::input::{Action, ActionState};
+use super::smoothing;
 
 pub fn manager(
-    mut camera: Query<(&mut OrbitCamera, &mut CameraInputs), With<OrbitCamera>>,
-    input: Res<Input<KeyCode>>,
+    mut camera: Query<&mut OrbitCamera, With<OrbitCamera>>,
+    actions: Res<ActionState>,
+    time: Res<Time>,
 ) {
-    for (mut orbit_camera, inputs) in camera.iter_mut() {
+    for mut orbit_camera in camera.iter_mut() {
+
+        // -- While spectating the zoom keys change the flying speed instead
+        if orbit_camera.camera_mode == CameraMode::Spectator {
+            let mut speed = orbit_camera.spectator_speed;
+
+            if actions.just_pressed(Action::ZoomIn) { speed *= 1.5; }
+            if actions.just_pressed(Action::ZoomOut) { speed /= 1.5; }
+
+            orbit_camera.spectator_speed = speed
+                .clamp(orbit_camera.spectator_speed_min, orbit_camera.spectator_speed_max);
+
+            continue;
+        }
 
         // -- Camera distance increment
-        if input.just_pressed(inputs.zoom_out) {
+        if actions.just_pressed(Action::ZoomOut) {
 
             if orbit_camera.camera_step < orbit_camera.camera_step_max {
                 // -- Step the camera distance up
@@ -24,7 +40,7 @@ pub fn manager(
         }
 
         // -- Camera distance decrement 
-        if input.just_pressed(inputs.zoom_in) 
+        if actions.just_pressed(Action::ZoomIn) 
         {
             if orbit_camera.camera_step < 1 {
                 // -- Dont decrement the camera distance below 0
@@ -39,12 +55,11 @@ pub fn manager(
             }
         }
 
-        // -- Lerp camera distance
-        orbit_camera.camera_distance = bevy_easings::Lerp::lerp(
-            &orbit_camera.camera_distance,
-            &((orbit_camera.camera_step * orbit_camera.camera_step_distance) as f32),
-            &orbit_camera.camera_step_lerp,
-        );
+        // -- Ease the camera distance towards the step
+        let target = (orbit_camera.camera_step * orbit_camera.camera_step_distance) as f32;
+        let t = smoothing(orbit_camera.distance_half_life, time.delta_seconds());
+
+        orbit_camera.camera_distance += (target - orbit_camera.camera_distance) * t;
 
     }
 }
\ No newline at end of file
@@ -0,0 +1,36 @@
+use bevy::{prelude::Transform, math::Vec3};
+
+use crate::components::OrbitCamera;
+use super::orbital_camera::calculate_orbit;
+
+// -- Moves the camera from wherever the orbit put it towards the players eyes,
+// by how far the transition into first person has come --
+pub fn manager(
+    orbital_camera: &mut OrbitCamera,
+    transform: &mut Transform,
+    player_transform: Transform,
+) {
+    let blend = smoothstep(orbital_camera.first_person_blend);
+    if blend <= 0.0 { return; }
+
+    let eye = player_transform.translation + orbital_camera.first_person_offset;
+
+    // -- Look the same way the orbit camera does, so nothing jumps mid transition
+    let look = -calculate_orbit(
+        orbital_camera.horizontal_angle,
+        orbital_camera.vertical_angle,
+        1.0,
+    );
+
+    let position = transform.translation.lerp(eye, blend);
+
+    transform.translation = position;
+    transform.look_at(position + look, Vec3::Y);
+
+    orbital_camera.camera_position = position;
+}
+
+fn smoothstep(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -15,7 +15,7 @@ pub fn manager(
     let orbit = calculate_orbit(
         orbital_camera.horizontal_angle, 
         orbital_camera.vertical_angle,
-        orbital_camera.camera_distance,
+        orbital_camera.arm_length,
     );
 
     let pos = Vec3::new(
@@ -37,6 +37,25 @@ pub fn manager(
 }
 
 
+// -- Pull the camera in to the first obstruction straight away, and ease
+// it back out to the wanted distance once there is room again --
+pub fn spring_arm(
+    orbital_camera: &mut OrbitCamera,
+    obstruction: Option<f32>,
+    delta: f32,
+) {
+    let wanted = orbital_camera.camera_distance;
+    let allowed = obstruction.map_or(wanted, |distance| distance.min(wanted)).max(0.0);
+
+    if allowed < orbital_camera.arm_length {
+        orbital_camera.arm_length = allowed;
+    } else {
+        let step = orbital_camera.arm_return_speed * delta;
+        orbital_camera.arm_length = (orbital_camera.arm_length + step).min(allowed);
+    }
+}
+
+
 // -- THis function is used to determine the position of the camera on
 // the xz and xy planes based on the given angle and distance.
 // https://math.stackexchange.com/questions/989900/calculate-x-y-z-from-two-specific-degrees-on-a-sphere
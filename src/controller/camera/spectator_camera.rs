@@ -0,0 +1,50 @@
+use bevy::{prelude::Transform, math::{Quat, Vec3}};
+use bevy_rapier3d::prelude::{Collider, RapierContext, QueryFilter};
+
+use crate::components::OrbitCamera;
+use super::orbital_camera::calculate_orbit;
+
+// -- Flies the camera along where it is looking, `movement` is x right,
+// y up and z forward. With noclip off the camera stops at anything solid --
+pub fn manager(
+    orbital_camera: &mut OrbitCamera,
+    transform: &mut Transform,
+    movement: Vec3,
+    delta: f32,
+    rapier_context: &RapierContext,
+) {
+    let forward = -calculate_orbit(
+        orbital_camera.horizontal_angle,
+        orbital_camera.vertical_angle,
+        1.0,
+    );
+    let right = forward.cross(Vec3::Y).normalize_or_zero();
+
+    let velocity = (right * movement.x + Vec3::Y * movement.y + forward * movement.z)
+        .clamp_length_max(1.0) * orbital_camera.spectator_speed;
+
+    let mut distance = velocity.length() * delta;
+
+    if !orbital_camera.noclip && distance > 0.0 {
+        let hit = rapier_context.cast_shape(
+            transform.translation,
+            Quat::IDENTITY,
+            velocity.normalize(),
+            &Collider::ball(orbital_camera.collision_radius),
+            distance,
+            QueryFilter::default().exclude_sensors(),
+        );
+
+        // -- Stop just short of whatever was hit
+        if let Some((_, toi)) = hit {
+            distance = (toi.toi - 0.01).max(0.0);
+        }
+    }
+
+    let position = transform.translation + velocity.normalize_or_zero() * distance;
+
+    transform.translation = position;
+    transform.look_at(position + forward, Vec3::Y);
+
+    orbital_camera.camera_position = position;
+}
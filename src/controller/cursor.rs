@@ -0,0 +1,97 @@
+use bevy::{
+    ecs::{event::{Events, ManualEventReader}, system::SystemParam},
+    prelude::*,
+    window::WindowFocused,
+};
+
+use crate::state::GameState;
+use super::input::{Action, ActionState, Binding, InputMap};
+
+/// Whether the cursor is locked to the window for looking around. Mouse
+/// motion only reaches the camera while it is grabbed.
+pub struct CursorGrab {
+    pub grabbed: bool,
+
+    // -- The player wants it grabbed, so it comes back when the window refocuses
+    pub wanted: bool,
+    pub focused: bool,
+}
+
+impl Default for CursorGrab {
+    fn default() -> Self {
+        Self { grabbed: false, wanted: false, focused: true }
+    }
+}
+
+// -- The inputs the manager reacts to. Focus events are optional, they only
+// exist when WindowPlugin is added --
+#[derive(SystemParam)]
+pub struct GrabInput<'w, 's> {
+    mouse_buttons: ResMut<'w, Input<MouseButton>>,
+    keys: Res<'w, Input<KeyCode>>,
+    actions: ResMut<'w, ActionState>,
+    input_map: Res<'w, InputMap>,
+
+    focus_events: Option<Res<'w, Events<WindowFocused>>>,
+    focus_reader: Local<'s, ManualEventReader<WindowFocused>>,
+}
+
+// -- Grab on a click into the window, let go on Escape or when focus is lost.
+// Only ever grabbed in game, and grabbed straight away when the game starts or resumes.
+// Runs after the actions are updated, so it sees this frame's toggle --
+pub fn manager(
+    mut grab: ResMut<CursorGrab>,
+    state: Res<State<GameState>>,
+    mut previous_state: Local<Option<GameState>>,
+    windows: Option<ResMut<Windows>>,
+    mut input: GrabInput,
+) {
+    // -- Headless, or the window is already closed
+    let mut windows = match windows {
+        Some(windows) => windows,
+        None => {
+            grab.grabbed = false;
+            return;
+        }
+    };
+
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => {
+            grab.grabbed = false;
+            return;
+        }
+    };
+
+    if let Some(events) = &input.focus_events {
+        for event in input.focus_reader.iter(events) {
+            if event.id == window.id() { grab.focused = event.focused; }
+        }
+    }
+
+    let in_game = *state.current() == GameState::InGame;
+    let entered_game = in_game && *previous_state != Some(GameState::InGame);
+    *previous_state = Some(*state.current());
+
+    if !in_game || input.keys.just_pressed(KeyCode::Escape) {
+        grab.wanted = false;
+    } else if entered_game {
+        grab.wanted = true;
+    } else if input.actions.just_pressed(Action::ToggleCursor) {
+        grab.wanted = !grab.wanted;
+    } else if !grab.grabbed && grab.focused && input.mouse_buttons.just_pressed(MouseButton::Left) {
+        grab.wanted = true;
+
+        // -- The click was only for grabbing, dont let it dig
+        input.mouse_buttons.reset(MouseButton::Left);
+        input.actions.consume(&input.input_map, Binding::Mouse(MouseButton::Left));
+    }
+
+    let grabbed = grab.wanted && grab.focused;
+
+    if grabbed != grab.grabbed || window.cursor_locked() != grabbed {
+        window.set_cursor_lock_mode(grabbed);
+        window.set_cursor_visibility(!grabbed);
+        grab.grabbed = grabbed;
+    }
+}
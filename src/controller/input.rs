@@ -0,0 +1,289 @@
+use bevy::{
+    input::{
+        gamepad::{Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads},
+        mouse::{MouseMotion, MouseWheel},
+    },
+    app::AppExit,
+    ecs::system::SystemParam,
+    prelude::*,
+    utils::HashSet,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use crate::config;
+use super::cursor::CursorGrab;
+
+pub const INPUT_FILE: &str = "input.ron";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    ZoomIn,
+    ZoomOut,
+    ToggleCursor,
+    Dig,
+    Place,
+    FlyUp,
+    FlyDown,
+    ToggleSpectator,
+    ToggleNoclip,
+    Explode,
+    PourWater,
+    PourLava,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    MouseWheelUp,
+    MouseWheelDown,
+    Pad(GamepadButtonType),
+}
+
+// -- Shapes a stick: a radial dead zone, then the rest of the range
+// raised to `exponent` so small movements are more precise --
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct StickCurve {
+    pub dead_zone: f32,
+    pub exponent: f32,
+}
+
+impl StickCurve {
+    pub fn apply(&self, value: Vec2) -> Vec2 {
+        let length = value.length();
+        if length <= self.dead_zone { return Vec2::ZERO; }
+
+        let scaled = ((length - self.dead_zone) / (1.0 - self.dead_zone)).min(1.0);
+        value / length * scaled.powf(self.exponent)
+    }
+}
+
+/// Every binding and tuning value, saved to and loaded from input.ron in the
+/// config dir. Look values end up multiplied by `OrbitCamera::mouse_sensitivity`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputMap {
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+
+    // -- Look units per pixel of mouse movement
+    pub mouse_scale: f32,
+
+    // -- Look units per second with the right stick fully pushed
+    pub gamepad_look_speed: f32,
+
+    pub move_stick: StickCurve,
+    pub look_stick: StickCurve,
+
+    // -- The (x, y) axes of the sticks that move and look around
+    pub move_axes: (GamepadAxisType, GamepadAxisType),
+    pub look_axes: (GamepadAxisType, GamepadAxisType),
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        use Action::*;
+        use Binding::*;
+
+        let bindings = [
+            (MoveForward, vec![Key(KeyCode::W)]),
+            (MoveBack, vec![Key(KeyCode::S)]),
+            (MoveLeft, vec![Key(KeyCode::A)]),
+            (MoveRight, vec![Key(KeyCode::D)]),
+            (Jump, vec![Key(KeyCode::Space), Pad(GamepadButtonType::South)]),
+            (ZoomIn, vec![Key(KeyCode::X), MouseWheelUp, Pad(GamepadButtonType::DPadUp)]),
+            (ZoomOut, vec![Key(KeyCode::Z), MouseWheelDown, Pad(GamepadButtonType::DPadDown)]),
+            (ToggleCursor, vec![Key(KeyCode::B), Pad(GamepadButtonType::Select)]),
+            (Dig, vec![Mouse(MouseButton::Left), Pad(GamepadButtonType::RightTrigger2)]),
+            (Place, vec![Mouse(MouseButton::Right), Pad(GamepadButtonType::LeftTrigger2)]),
+            (FlyUp, vec![Key(KeyCode::E), Pad(GamepadButtonType::RightTrigger)]),
+            (FlyDown, vec![Key(KeyCode::Q), Pad(GamepadButtonType::LeftTrigger)]),
+            (ToggleSpectator, vec![Key(KeyCode::F1)]),
+            (ToggleNoclip, vec![Key(KeyCode::N)]),
+            (Explode, vec![Key(KeyCode::G), Pad(GamepadButtonType::West)]),
+            (PourWater, vec![Key(KeyCode::R)]),
+            (PourLava, vec![Key(KeyCode::T)]),
+        ];
+
+        Self {
+            bindings: bindings.into_iter().collect(),
+            mouse_scale: 0.01,
+            gamepad_look_speed: 4.0,
+            move_stick: StickCurve { dead_zone: 0.15, exponent: 1.0 },
+            look_stick: StickCurve { dead_zone: 0.1, exponent: 2.0 },
+            move_axes: (GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY),
+            look_axes: (GamepadAxisType::RightStickX, GamepadAxisType::RightStickY),
+        }
+    }
+}
+
+impl InputMap {
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) { bindings.push(binding); }
+    }
+
+    pub fn unbind(&mut self, action: Action, binding: Binding) {
+        if let Some(bindings) = self.bindings.get_mut(&action) {
+            bindings.retain(|bound| *bound != binding);
+        }
+    }
+}
+
+/// The state of every action this frame, read this instead of the raw inputs.
+#[derive(Default, Debug)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    just_released: HashSet<Action>,
+
+    // -- x is right, y is forward, length at most 1
+    pub movement: Vec2,
+
+    // -- Accumulated look this frame, x is yaw and y is pitch
+    pub look: Vec2,
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        self.just_released.contains(&action)
+    }
+
+    // -- Drop the presses that came from this binding, for input something
+    // else already used. They stay held, so they don't fire again next frame --
+    pub fn consume(&mut self, input_map: &InputMap, binding: Binding) {
+        for (action, bindings) in input_map.bindings.iter() {
+            if bindings.contains(&binding) { self.just_pressed.remove(action); }
+        }
+    }
+}
+
+pub fn load_input_map() -> InputMap {
+    let mut input_map: InputMap = config::load(INPUT_FILE);
+
+    // -- Actions added since the file was written get their default bindings
+    for (action, bindings) in InputMap::default().bindings {
+        input_map.bindings.entry(action).or_insert(bindings);
+    }
+
+    input_map
+}
+
+// -- Write the bindings back out when the game closes --
+pub fn save_input_map(
+    mut exit: EventReader<AppExit>,
+    input_map: Res<InputMap>,
+) {
+    if exit.iter().next().is_some() {
+        config::save(INPUT_FILE, &*input_map);
+    }
+}
+
+#[derive(SystemParam)]
+pub struct MouseInput<'w, 's> {
+    buttons: Res<'w, Input<MouseButton>>,
+    motion: EventReader<'w, 's, MouseMotion>,
+    wheel: EventReader<'w, 's, MouseWheel>,
+}
+
+#[derive(SystemParam)]
+pub struct GamepadInput<'w, 's> {
+    buttons: Res<'w, Input<GamepadButton>>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+    gamepads: Res<'w, Gamepads>,
+
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+pub fn update_actions(
+    mut state: ResMut<ActionState>,
+    input_map: Res<InputMap>,
+    keys: Res<Input<KeyCode>>,
+    mut mouse: MouseInput,
+    gamepad: GamepadInput,
+    cursor: Res<CursorGrab>,
+    time: Res<Time>,
+) {
+    let wheel: f32 = mouse.wheel.iter().map(|event| event.y).sum();
+    let gamepads: Vec<Gamepad> = gamepad.gamepads.iter().copied().collect();
+
+    // -- (held, pressed this frame) for a single binding
+    let binding_state = |binding: &Binding| -> (bool, bool) {
+        match binding {
+            Binding::Key(key) => (keys.pressed(*key), keys.just_pressed(*key)),
+            Binding::Mouse(button) => (mouse.buttons.pressed(*button), mouse.buttons.just_pressed(*button)),
+            Binding::MouseWheelUp => (wheel > 0.0, wheel > 0.0),
+            Binding::MouseWheelDown => (wheel < 0.0, wheel < 0.0),
+            Binding::Pad(button) => gamepads.iter().fold((false, false), |(held, pressed), pad| {
+                let button = GamepadButton(*pad, *button);
+                (held || gamepad.buttons.pressed(button), pressed || gamepad.buttons.just_pressed(button))
+            }),
+        }
+    };
+
+    let previous = std::mem::take(&mut state.pressed);
+    state.just_pressed.clear();
+    state.just_released.clear();
+
+    for (action, bindings) in input_map.bindings.iter() {
+        let (held, pressed) = bindings.iter()
+            .map(binding_state)
+            .fold((false, false), |a, b| (a.0 || b.0, a.1 || b.1));
+
+        if held { state.pressed.insert(*action); }
+        if pressed || (held && !previous.contains(action)) { state.just_pressed.insert(*action); }
+    }
+
+    for action in previous.iter() {
+        if !state.pressed.contains(action) { state.just_released.insert(*action); }
+    }
+
+    // -- Movement, the keys and the left stick added together
+    let axis = |positive: Action, negative: Action| {
+        state.pressed(positive) as i32 as f32 - state.pressed(negative) as i32 as f32
+    };
+
+    let mut movement = Vec2::new(
+        axis(Action::MoveRight, Action::MoveLeft),
+        axis(Action::MoveForward, Action::MoveBack),
+    );
+
+    let stick = |pad: Gamepad, (x, y): (GamepadAxisType, GamepadAxisType)| Vec2::new(
+        gamepad.axes.get(GamepadAxis(pad, x)).unwrap_or(0.0),
+        gamepad.axes.get(GamepadAxis(pad, y)).unwrap_or(0.0),
+    );
+
+    let mut look = Vec2::ZERO;
+
+    for pad in gamepads.iter() {
+        movement += input_map.move_stick.apply(stick(*pad, input_map.move_axes));
+
+        let right = input_map.look_stick.apply(stick(*pad, input_map.look_axes));
+
+        // -- Stick up should match moving the mouse up, which is a negative delta
+        look += Vec2::new(right.x, -right.y) * input_map.gamepad_look_speed * time.delta_seconds();
+    }
+
+    // -- The mouse only looks around while the cursor is grabbed, the events
+    // are still read so they dont pile up for when it is
+    for event in mouse.motion.iter() {
+        if cursor.grabbed { look += event.delta * input_map.mouse_scale; }
+    }
+
+    state.movement = movement.clamp_length_max(1.0);
+    state.look = look;
+}
@@ -1,23 +1,52 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, input::InputSystem};
 // use bevy_inspector_egui::*;
 // use bevy_prototype_debug_lines::DebugLines;
-use bevy_rapier3d::prelude::{Collider, Velocity, GravityScale, Sleeping, Ccd, RigidBody, LockedAxes};
+use bevy_rapier3d::prelude::{
+    Collider, Velocity, GravityScale, Sleeping, Ccd, RigidBody, LockedAxes,
+    Friction, CoefficientCombineRule, RapierContext, QueryFilter,
+};
 use crate::components::*;
+use crate::state::GameState;
+use crate::terrain_engine::{
+    chunk::{self, Chunk},
+    chunk_manager::{ChunkManager, ChunkMeshed},
+    edit::VoxelEdit,
+    explosion::Explosion,
+    fluid::{self, buoyancy::Buoyancy, FluidEdit},
+    generator::TerrainGenerator,
+    material,
+    raycast::VoxelTarget,
+};
+use input::{Action, ActionState};
  
 mod camera;
+pub mod cursor;
+pub mod input;
 
 pub struct CharacterControllerPlugin;
 
 impl Plugin for CharacterControllerPlugin {
     fn build(&self, app: &mut App) {
+        app.insert_resource(input::load_input_map());
+        app.init_resource::<ActionState>();
+        app.init_resource::<cursor::CursorGrab>();
+        app.add_system_to_stage(CoreStage::PreUpdate, input::update_actions.after(InputSystem));
+        app.add_system_to_stage(CoreStage::PreUpdate, cursor::manager.after(input::update_actions));
+        app.add_system_to_stage(CoreStage::Last, input::save_input_map);
+
         app.add_startup_system_to_stage(StartupStage::PostStartup, instantiate_character_controller);
 
-        app.add_system(character_controller);
-        app.add_system(camera::camera_distance::manager);
-        app.add_system(camera::manager);
+        app.add_system_set(
+            SystemSet::on_update(GameState::InGame)
+                .with_system(character_controller)
+                .with_system(voxel_interaction)
+                .with_system(camera::camera_distance::manager)
+                .with_system(camera::manager)
+        );
 
         app.register_type::<OrbitCamera>();
         app.register_type::<Player>();
+        app.register_type::<CharacterController>();
 
         app.init_resource::<CameraMode>();  
 
@@ -28,73 +57,136 @@ impl Plugin for CharacterControllerPlugin {
 
 
 pub fn character_controller(
-    mut query: ParamSet<(
-        Query<(&mut Transform), With<Player>>,
-        Query<&mut OrbitCamera, With<OrbitCamera>>,
-    )>,
-    mut windows: ResMut<Windows>,
-    // mut lines: ResMut<DebugLines>,
-    input: Res<Input<KeyCode>>,
+    mut players: Query<(Entity, &Transform, &mut Velocity, &mut CharacterController), With<Player>>,
+    camera: Query<&OrbitCamera>,
+    terrain: Query<(), (With<Chunk>, With<ChunkMeshed>)>,
+    manager: Res<ChunkManager>,
+    rapier_context: Res<RapierContext>,
+    time: Res<Time>,
+    actions: Res<ActionState>,
 ) {
-    let mut camera = OrbitCamera::default();
+    let horizontal_angle = camera.iter().next()
+        .map(|orbit_camera| orbit_camera.horizontal_angle)
+        .unwrap_or(0.0);
 
-    for orbit_camera in query.p1().iter_mut() {
-        camera = orbit_camera.clone();
-    }       
+    // -- The movement keys fly the camera while spectating
+    let spectating = camera.iter().any(|orbit_camera| orbit_camera.camera_mode == CameraMode::Spectator);
 
+    // -- The camera sits at (sin, cos) of the angle around the player,
+    // so forward is the opposite of that
+    let angle = horizontal_angle.to_radians();
+    let forward = -Vec3::new(angle.sin(), 0.0, angle.cos());
+    let right = forward.cross(Vec3::Y);
 
-    for (mut transform) in query.p0().iter_mut() {
-        
-        let mut translation = transform.translation;
+    // -- Already clamped to a length of 1, but can be less on a stick
+    let wish = if spectating { Vec3::ZERO } else { forward * actions.movement.y + right * actions.movement.x };
 
-        // -- START DEBUG --
-        let mut orbit = camera::orbital_camera::calculate_orbit(
-            transform.rotation.x.to_degrees() + 90.0,
-            0.0,
-            10.0,
-        );
+    let delta = time.delta_seconds();
 
-        orbit.x += translation.x;
-        orbit.y += translation.y;
-        orbit.z += translation.z;
+    for (entity, transform, mut velocity, mut controller) in players.iter_mut() {
+
+        // -- Hold still until the chunk under the player has been meshed once, an
+        // empty chunk never gets a collider so that cannot be waited on
+        let below = chunk::chunk_coord(chunk::world_to_voxel(transform.translation - Vec3::Y * controller.half_height));
+        let ground_loaded = manager.get(below).map_or(false, |chunk| terrain.get(chunk).is_ok());
+
+        if !ground_loaded {
+            velocity.linvel = Vec3::ZERO;
+            continue;
+        }
 
-        // lines.line_colored(
-        //     translation,
-        //     orbit, 
-        //     0.0,
-        //     Color::CYAN
-        // );
-        // -- END DEBUG --
+        let filter = QueryFilter::default().exclude_rigid_body(entity);
+        let feet = transform.translation - Vec3::Y * controller.half_height;
+
+        // -- Grounded check, a short ray down from the center
+        let max_slope = controller.max_slope.to_radians().cos();
+        let ground = rapier_context.cast_ray_and_get_normal(
+            transform.translation,
+            -Vec3::Y,
+            controller.half_height + 0.1,
+            true,
+            filter,
+        );
 
+        controller.ground_normal = ground.map_or(Vec3::Y, |(_, hit)| hit.normal);
+        controller.grounded = ground.is_some() && controller.ground_normal.dot(Vec3::Y) >= max_slope;
 
-        // if input.pressed(KeyCode::W) {
-        //     velocity.linvel.x += 1.5;
-        // }
+        // -- Too steep to walk up, drop the part of the input going into the slope
+        let mut wish = wish;
+        if ground.is_some() && !controller.grounded {
+            let downhill = Vec3::new(controller.ground_normal.x, 0.0, controller.ground_normal.z).normalize_or_zero();
+            let into_slope = wish.dot(-downhill);
 
-        // if input.pressed(KeyCode::S) {
-        //     velocity.linvel.x = -1.0;
-        // }
+            if into_slope > 0.0 { wish += downhill * into_slope; }
+        }
 
-        // if input.pressed(KeyCode::A) {
-        //     velocity.linvel.z = 1.5;
-        // }
+        // -- Step up small ledges, if the feet are blocked but the step height is clear.
+        // Rising is left to the physics, fast enough to cover the rest of the step in step_time
+        if controller.grounded && wish != Vec3::ZERO {
+            let reach = controller.radius + 0.1;
+            let direction = wish.normalize();
+            let low = rapier_context.cast_ray(feet + Vec3::Y * 0.05, direction, reach, true, filter);
+            let high = rapier_context.cast_ray(feet + Vec3::Y * controller.step_height, direction, reach, true, filter);
+
+            if low.is_some() && high.is_none() {
+                let top = feet + direction * reach + Vec3::Y * controller.step_height;
+
+                if let Some((_, toi)) = rapier_context.cast_ray(top, -Vec3::Y, controller.step_height, true, filter) {
+                    let rise = controller.step_height - toi + 0.01;
+                    velocity.linvel.y = velocity.linvel.y.max(rise / controller.step_time);
+                }
+            }
+        }
 
-        // if input.pressed(KeyCode::D) {
-        //     velocity.linvel.z = -1.5;
-        // }
+        // -- Accelerate the horizontal velocity towards the input
+        let target = wish * controller.speed;
+        let acceleration = if controller.grounded { controller.acceleration } else { controller.air_acceleration };
 
+        let horizontal = Vec3::new(velocity.linvel.x, 0.0, velocity.linvel.z);
+        let change = target - horizontal;
+        let horizontal = horizontal + change.clamp_length_max(acceleration * delta);
 
-        // -- Toggle mouse cursor 
-        let window = windows.get_primary_mut().unwrap();
+        velocity.linvel.x = horizontal.x;
+        velocity.linvel.z = horizontal.z;
 
-        if input.pressed(KeyCode::B) {
-            window.set_cursor_lock_mode(true);
-            window.set_cursor_visibility(false);
+        if controller.grounded && !spectating && actions.just_pressed(Action::Jump) {
+            velocity.linvel.y = controller.jump_speed;
         }
+    }
+}
 
-        if input.pressed(KeyCode::N) {
-            window.set_cursor_lock_mode(false);
-            window.set_cursor_visibility(true);
+// -- Dig out or build onto whatever the camera is aiming at --
+pub fn voxel_interaction(
+    actions: Res<ActionState>,
+    target: Res<VoxelTarget>,
+    mut edits: EventWriter<VoxelEdit>,
+    mut explosions: EventWriter<Explosion>,
+    mut fluids: EventWriter<FluidEdit>,
+) {
+    let hit = match target.hit {
+        Some(hit) => hit,
+        None => return,
+    };
+
+    if actions.just_pressed(Action::Dig) {
+        edits.send(VoxelEdit::dig(hit.point, 1.5));
+    }
+
+    if actions.just_pressed(Action::Place) {
+        let material = if hit.material == material::AIR { material::DIRT } else { hit.material };
+        edits.send(VoxelEdit::fill(hit.point + hit.normal * 0.5, 1.5, material));
+    }
+
+    if actions.just_pressed(Action::Explode) {
+        explosions.send(Explosion { center: hit.point, radius: 4.0, power: 150.0 });
+    }
+
+    // -- Poured into the air in front of the surface
+    let pour = [(Action::PourWater, fluid::WATER), (Action::PourLava, fluid::LAVA)];
+
+    for (action, kind) in pour {
+        if actions.just_pressed(action) {
+            fluids.send(FluidEdit { center: hit.point + hit.normal * 1.5, radius: 1.5, kind });
         }
     }
 }
@@ -104,6 +196,7 @@ pub fn instantiate_character_controller(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    generator: Res<TerrainGenerator>,
 ) {
     
     // -- Camera
@@ -111,33 +204,45 @@ pub fn instantiate_character_controller(
         ..default()
     })
     .insert(OrbitCamera::default())      
-    .insert(Rotation::zero())
-    .insert(CameraInputs::default());
+    .insert(Rotation::zero());
+
+    // -- Player, dropped just above the terrain
+    let controller = CharacterController::default();
+    let spawn_height = generator.height(0, 0) + controller.half_height + 0.5;
 
-    // -- Player
     commands.spawn_bundle(PbrBundle {
         mesh: meshes.add(Mesh::from(shape::Capsule { 
-            radius: 0.5, 
-            depth: 1.0, 
+            radius: controller.radius,
+            depth: (controller.half_height - controller.radius) * 2.0,
             ..Default::default()
         })),
-        transform: Transform::from_xyz(0.0, 1.0, 0.0),
+        transform: Transform::from_xyz(0.0, spawn_height, 0.0),
         material: materials.add(Color::rgb(0.1, 0.2, 0.6).into()),
         ..default()
     })
-    .insert(Player);
-    // .insert(Collider::capsule(
-    //     Vec3::new(0.0, -0.5, 0.0),
-    //     Vec3::new(0.0, 0.5, 0.0),
-    //     0.5,
-    // ))
-    // .insert(RigidBody::Dynamic)
-    // .insert(Velocity {
-    //     linvel: Vec3::new(1.0, 2.0, 3.0),
-    //     angvel: Vec3::new(0.0, 0.0, 0.0),
-    // })
-    // .insert(GravityScale(0.7))
-    // .insert(Sleeping::disabled())
-    // .insert(Ccd::enabled())
-    // .insert(LockedAxes::ROTATION_LOCKED);
-}   
\ No newline at end of file
+    .insert(Player)
+    .insert(Collider::capsule(
+        Vec3::new(0.0, -(controller.half_height - controller.radius), 0.0),
+        Vec3::new(0.0, controller.half_height - controller.radius, 0.0),
+        controller.radius,
+    ))
+    .insert(RigidBody::Dynamic)
+    .insert(Velocity::default())
+    .insert(GravityScale(1.0))
+    .insert(Sleeping::disabled())
+    .insert(Ccd::enabled())
+    .insert(LockedAxes::ROTATION_LOCKED)
+    // -- No friction, the controller handles stopping and it keeps the
+    // capsule from sticking to walls
+    .insert(Friction {
+        coefficient: 0.0,
+        combine_rule: CoefficientCombineRule::Min,
+    })
+    // -- A little lighter than water, so the player floats
+    .insert(Buoyancy {
+        density: 0.9,
+        center: Vec3::ZERO,
+        half_height: controller.half_height,
+    })
+    .insert(controller);
+}   
@@ -1,51 +1,49 @@
 use bevy::{prelude::*, math::vec3};
 use bevy_easings::EasingsPlugin;
+use bevy_rapier3d::prelude::{RapierPhysicsPlugin, NoUserData};
 
 mod components;
+mod config;
 mod controller;
+mod settings;
+mod state;
 mod terrain_engine;   
+mod ui;
 
 /// set up a simple 3D scene
 fn setup(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
+    settings: Res<settings::Settings>,
 ) {
-    // plane
-    commands.spawn_bundle(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Plane { size: 100.0 })),
-        transform: Transform { translation: vec3(0.0, -1.0, 0.0), ..Default::default() },
-        material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
-        ..default()
-    })
-    // .insert(Collider::cuboid(100.0, 0.1, 100.0))
-    .insert_bundle(TransformBundle::from(Transform::from_xyz(0.0, -1.0, 0.0)));
-
-
     // light
+    let graphics = &settings.graphics;
+
     commands.spawn_bundle(PointLightBundle {
         point_light: PointLight {
-            intensity: 15500.0,
-            shadows_enabled: true,
-            range: 500.0,
-            radius: 40.0,
+            intensity: graphics.light_intensity,
+            shadows_enabled: graphics.shadows,
+            range: graphics.light_range,
+            radius: graphics.light_radius,
             ..default()
         },
         transform: Transform::from_xyz(0.0, 50.0, 14.0),
         ..default()
-    });
+    })
+    .insert(components::Sun);
 }
 
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
-        // .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+        .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
         // .add_plugin(RapierDebugRenderPlugin::default())
 
         .add_plugin(controller::CharacterControllerPlugin)
         .add_plugin(terrain_engine::VoxelEnginePlugin)
         .add_plugin(terrain_engine::chunk::marching_cube::ComputePlugin)
-        .insert_resource(Msaa { samples: 4 })
+        .add_plugin(settings::SettingsPlugin)
+        .add_plugin(state::GameStatePlugin)
+        .add_plugin(ui::UiPlugin)
         .add_plugin(EasingsPlugin)
         .add_startup_system(setup)  
         // .add_plugin(DebugLinesPlugin::default())  
@@ -0,0 +1,157 @@
+use bevy::{app::AppExit, prelude::*, render::camera::Projection};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::{OrbitCamera, Sun},
+    config,
+    terrain_engine::chunk_manager::ChunkManager,
+};
+
+pub const SETTINGS_FILE: &str = "settings.ron";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraSettings {
+    pub step_distance: u32,
+    pub step_max: u32,
+
+    // -- Smoothing half-lives in seconds, see OrbitCamera
+    pub distance_half_life: f32,
+    pub angle_half_life: f32,
+    pub follow_half_life: f32,
+
+    // -- Vertical field of view in degrees
+    pub fov: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            step_distance: 5,
+            step_max: 5,
+            distance_half_life: 0.08,
+            angle_half_life: 0.02,
+            follow_half_life: 0.03,
+            fov: 45.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsSettings {
+    pub msaa_samples: u32,
+
+    // -- In chunks, see ChunkManager::view_radius
+    pub render_distance: i32,
+
+    pub light_intensity: f32,
+    pub light_range: f32,
+    pub light_radius: f32,
+    pub shadows: bool,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            msaa_samples: 4,
+            render_distance: 6,
+            light_intensity: 15500.0,
+            light_range: 500.0,
+            light_radius: 40.0,
+            shadows: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlSettings {
+    pub mouse_sensitivity: f32,
+    pub inverted_x: bool,
+    pub inverted_y: bool,
+}
+
+impl Default for ControlSettings {
+    fn default() -> Self {
+        Self {
+            mouse_sensitivity: 20.0,
+            inverted_x: false,
+            inverted_y: false,
+        }
+    }
+}
+
+/// Everything the player can tweak, kept in settings.ron in the config dir.
+/// Changing this resource applies it to the camera, chunk manager and lights.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub camera: CameraSettings,
+    pub graphics: GraphicsSettings,
+    pub controls: ControlSettings,
+}
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let settings: Settings = config::load(SETTINGS_FILE);
+
+        app.insert_resource(Msaa { samples: settings.graphics.msaa_samples });
+        app.insert_resource(settings);
+
+        app.add_system(apply_settings);
+        app.add_system_to_stage(CoreStage::Last, save_settings);
+    }
+}
+
+pub fn apply_settings(
+    settings: Res<Settings>,
+    mut msaa: ResMut<Msaa>,
+    mut manager: ResMut<ChunkManager>,
+    mut cameras: Query<(&mut OrbitCamera, &mut Projection)>,
+    mut lights: Query<&mut PointLight, With<Sun>>,
+) {
+    if !settings.is_changed() { return; }
+
+    if msaa.samples != settings.graphics.msaa_samples {
+        msaa.samples = settings.graphics.msaa_samples;
+    }
+
+    manager.view_radius = settings.graphics.render_distance;
+
+    for (mut orbit_camera, mut projection) in cameras.iter_mut() {
+        orbit_camera.mouse_sensitivity = settings.controls.mouse_sensitivity;
+        orbit_camera.inverted_x = settings.controls.inverted_x;
+        orbit_camera.inverted_y = settings.controls.inverted_y;
+
+        orbit_camera.camera_step_distance = settings.camera.step_distance;
+        orbit_camera.distance_half_life = settings.camera.distance_half_life;
+        orbit_camera.angle_half_life = settings.camera.angle_half_life;
+        orbit_camera.follow_half_life = settings.camera.follow_half_life;
+        orbit_camera.camera_step_max = settings.camera.step_max;
+        orbit_camera.camera_step = orbit_camera.camera_step.min(settings.camera.step_max);
+
+        if let Projection::Perspective(perspective) = &mut *projection {
+            perspective.fov = settings.camera.fov.to_radians();
+        }
+    }
+
+    for mut light in lights.iter_mut() {
+        light.intensity = settings.graphics.light_intensity;
+        light.range = settings.graphics.light_range;
+        light.radius = settings.graphics.light_radius;
+        light.shadows_enabled = settings.graphics.shadows;
+    }
+}
+
+// -- Write the settings back out when the game closes --
+pub fn save_settings(
+    mut exit: EventReader<AppExit>,
+    settings: Res<Settings>,
+) {
+    if exit.iter().next().is_some() {
+        config::save(SETTINGS_FILE, &*settings);
+    }
+}
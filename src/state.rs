@@ -0,0 +1,133 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::RapierConfiguration;
+
+use crate::{
+    components::Player,
+    terrain_engine::{
+        chunk::marching_cube::MarchingCubeOutput,
+        chunk_manager::{self, ChunkManager, ChunkMeshed},
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameState {
+    // -- Waiting on the compute pipeline and the chunks around the spawn
+    Loading,
+    MainMenu,
+    InGame,
+
+    // -- Pushed on top of InGame, so popping it resumes where it left off
+    Paused,
+}
+
+/// How far along loading is, read by the loading screen.
+pub struct LoadingProgress {
+    // -- Radius in chunks around the player that has to be meshed first
+    pub spawn_radius: i32,
+
+    pub pipeline_ready: bool,
+    pub meshed: usize,
+    pub total: usize,
+}
+
+impl Default for LoadingProgress {
+    fn default() -> Self {
+        Self {
+            spawn_radius: 2,
+            pipeline_ready: false,
+            meshed: 0,
+            total: 0,
+        }
+    }
+}
+
+pub struct GameStatePlugin;
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(GameState::Loading);
+        app.init_resource::<LoadingProgress>();
+
+        app.add_system(sync_physics);
+        app.add_system_set(SystemSet::on_update(GameState::Loading).with_system(loading));
+        app.add_system_set(SystemSet::on_update(GameState::InGame).with_system(pause));
+        app.add_system_set(SystemSet::on_update(GameState::Paused).with_system(resume));
+    }
+}
+
+// -- Move on to the menu once the pipeline is up and the spawn is meshed --
+pub fn loading(
+    mut state: ResMut<State<GameState>>,
+    mut progress: ResMut<LoadingProgress>,
+    output: Res<MarchingCubeOutput>,
+    manager: Res<ChunkManager>,
+    meshed: Query<(), With<ChunkMeshed>>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let center = match chunk_manager::player_chunk(&player) {
+        Some(center) => center,
+        None => return,
+    };
+
+    // -- A pipeline that failed to compile falls back to CPU meshing
+    progress.pipeline_ready = !manager.gpu_meshing || output.is_ready() || output.is_failed();
+
+    let radius = progress.spawn_radius.min(manager.view_radius);
+    let vertical = radius.min(manager.vertical_radius);
+
+    progress.total = 0;
+    progress.meshed = 0;
+
+    for y in -vertical..=vertical {
+        for z in -radius..=radius {
+            for x in -radius..=radius {
+                let position = center + IVec3::new(x, y, z);
+                if !manager.in_range(center, position, 0) { continue; }
+
+                progress.total += 1;
+
+                if manager.get(position).map_or(false, |entity| meshed.get(entity).is_ok()) {
+                    progress.meshed += 1;
+                }
+            }
+        }
+    }
+
+    if progress.pipeline_ready && progress.meshed == progress.total {
+        let _ = state.set(GameState::MainMenu);
+    }
+}
+
+// -- The state stage reruns after a transition, so both of these reset the
+// press to keep it from toggling straight back --
+pub fn pause(
+    mut state: ResMut<State<GameState>>,
+    mut keys: ResMut<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        keys.reset(KeyCode::Escape);
+        let _ = state.push(GameState::Paused);
+    }
+}
+
+pub fn resume(
+    mut state: ResMut<State<GameState>>,
+    mut keys: ResMut<Input<KeyCode>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        keys.reset(KeyCode::Escape);
+        let _ = state.pop();
+    }
+}
+
+// -- Physics only steps while playing --
+pub fn sync_physics(
+    state: Res<State<GameState>>,
+    mut rapier_config: ResMut<RapierConfiguration>,
+) {
+    let active = *state.current() == GameState::InGame;
+
+    if rapier_config.physics_pipeline_active != active {
+        rapier_config.physics_pipeline_active = active;
+    }
+}
@@ -1,11 +1,282 @@
 use bevy::prelude::*;
+use std::sync::Arc;
+
+use super::{
+    fluid::{Fluid, FluidId},
+    material::{MaterialId, AIR},
+};
+use marching_cube::{MarchingCubeJob, MarchingCubeParams};
 
 pub mod marching_cube;
+pub mod marching_cube_table;
+pub mod mesher;
+
+// -- Number of cells along each axis of a chunk, one cell is one world unit --
+pub const CHUNK_SIZE: i32 = 32;
+
+// -- Extra samples stored past each border so normals line up with the neighbours --
+pub const CHUNK_PADDING: i32 = 1;
+
+// -- A chunk needs CHUNK_SIZE + 1 samples to mesh CHUNK_SIZE cells, plus the padding --
+pub const CHUNK_SAMPLES: i32 = CHUNK_SIZE + 1 + CHUNK_PADDING * 2;
+
+// -- Density above this is solid, below it is air --
+pub const ISO_LEVEL: f32 = 0.0;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Voxel {
+    pub density: f32,
+    pub material: MaterialId,
+}
+
+impl Voxel {
+    pub fn air() -> Self {
+        Self { density: -1.0, material: AIR }
+    }
+
+    pub fn is_solid(&self) -> bool {
+        self.density > ISO_LEVEL
+    }
+}
+
+/// A cube of density samples, material ids and fluid. Local coordinates run from
+/// `-CHUNK_PADDING` to `CHUNK_SIZE + CHUNK_PADDING` inclusive, where
+/// `0..=CHUNK_SIZE` are the samples owned by this chunk (the last one is shared
+/// with the next chunk along) and the rest is a copy of the neighbours' samples.
+#[derive(Component, Clone, Debug)]
+pub struct Chunk {
+    pub position: IVec3,
+    density: Vec<f32>,
+    material: Vec<MaterialId>,
+
+    // -- Fluid sits in the air between the solid samples, see `fluid`
+    fluid_level: Vec<u8>,
+    fluid_kind: Vec<FluidId>,
+
+    // -- Bumped whenever a density or material changes, to tell stale meshes apart
+    edits: u32,
+}
+
+impl Chunk {
+    pub fn new(position: IVec3) -> Self {
+        let len = (CHUNK_SAMPLES * CHUNK_SAMPLES * CHUNK_SAMPLES) as usize;
+        let air = Voxel::air();
+
+        Self {
+            position,
+            density: vec![air.density; len],
+            material: vec![air.material; len],
+            fluid_level: vec![0; len],
+            fluid_kind: vec![Fluid::default().kind; len],
+            edits: 0,
+        }
+    }
+
+    // -- World position of the chunks local (0, 0, 0) sample --
+    pub fn origin(&self) -> IVec3 {
+        self.position * CHUNK_SIZE
+    }
+
+    pub fn in_bounds(local: IVec3) -> bool {
+        let min = -CHUNK_PADDING;
+        let max = CHUNK_SIZE + CHUNK_PADDING;
+
+        local.cmpge(IVec3::splat(min)).all() && local.cmple(IVec3::splat(max)).all()
+    }
+
+    fn index(local: IVec3) -> usize {
+        let p = local + IVec3::splat(CHUNK_PADDING);
+        (p.x + p.y * CHUNK_SAMPLES + p.z * CHUNK_SAMPLES * CHUNK_SAMPLES) as usize
+    }
+
+    // region: --Local coordinates--
+
+    pub fn get(&self, local: IVec3) -> Option<Voxel> {
+        if !Self::in_bounds(local) { return None; }
+
+        let index = Self::index(local);
+        Some(Voxel { density: self.density[index], material: self.material[index] })
+    }
+
+    // -- Returns false if the sample is not stored in this chunk --
+    pub fn set(&mut self, local: IVec3, voxel: Voxel) -> bool {
+        if !Self::in_bounds(local) { return false; }
+
+        let index = Self::index(local);
+        self.density[index] = voxel.density;
+        self.material[index] = voxel.material;
+        self.edits = self.edits.wrapping_add(1);
+        true
+    }
+
+    pub fn density(&self, local: IVec3) -> Option<f32> {
+        self.get(local).map(|voxel| voxel.density)
+    }
+
+    pub fn set_density(&mut self, local: IVec3, density: f32) -> bool {
+        if !Self::in_bounds(local) { return false; }
+
+        self.density[Self::index(local)] = density;
+        self.edits = self.edits.wrapping_add(1);
+        true
+    }
+
+    pub fn material(&self, local: IVec3) -> Option<MaterialId> {
+        self.get(local).map(|voxel| voxel.material)
+    }
+
+    pub fn set_material(&mut self, local: IVec3, material: MaterialId) -> bool {
+        if !Self::in_bounds(local) { return false; }
+
+        self.material[Self::index(local)] = material;
+        self.edits = self.edits.wrapping_add(1);
+        true
+    }
+
+    pub fn fluid(&self, local: IVec3) -> Option<Fluid> {
+        if !Self::in_bounds(local) { return None; }
+
+        let index = Self::index(local);
+        Some(Fluid { level: self.fluid_level[index], kind: self.fluid_kind[index] })
+    }
+
+    // -- Returns false if the sample is not stored in this chunk --
+    pub fn set_fluid(&mut self, local: IVec3, fluid: Fluid) -> bool {
+        if !Self::in_bounds(local) { return false; }
+
+        let index = Self::index(local);
+        self.fluid_level[index] = fluid.level;
+        self.fluid_kind[index] = fluid.kind;
+        true
+    }
+
+    // endregion: --Local coordinates--
+
+    // region: --World coordinates--
+
+    pub fn world_to_local(&self, world: IVec3) -> IVec3 {
+        world - self.origin()
+    }
+
+    pub fn local_to_world(&self, local: IVec3) -> IVec3 {
+        local + self.origin()
+    }
+
+    pub fn get_world(&self, world: IVec3) -> Option<Voxel> {
+        self.get(self.world_to_local(world))
+    }
+
+    pub fn set_world(&mut self, world: IVec3, voxel: Voxel) -> bool {
+        self.set(self.world_to_local(world), voxel)
+    }
+
+    pub fn fluid_world(&self, world: IVec3) -> Option<Fluid> {
+        self.fluid(self.world_to_local(world))
+    }
+
+    pub fn set_fluid_world(&mut self, world: IVec3, fluid: Fluid) -> bool {
+        self.set_fluid(self.world_to_local(world), fluid)
+    }
+
+    // endregion: --World coordinates--
+
+    // -- Fill every sample, padding included, from a function of the world position --
+    pub fn fill(&mut self, mut f: impl FnMut(IVec3) -> Voxel) {
+        let min = -CHUNK_PADDING;
+        let max = CHUNK_SIZE + CHUNK_PADDING;
+
+        for z in min..=max {
+            for y in min..=max {
+                for x in min..=max {
+                    let local = IVec3::new(x, y, z);
+                    let voxel = f(self.local_to_world(local));
+                    self.set(local, voxel);
+                }
+            }
+        }
+    }
+
+    // -- Raw samples (x first, then y, then z) and their dimensions, for the meshers --
+    pub fn densities(&self) -> &[f32] {
+        &self.density
+    }
+
+    pub fn materials(&self) -> &[MaterialId] {
+        &self.material
+    }
+
+    pub fn fluid_levels(&self) -> &[u8] {
+        &self.fluid_level
+    }
+
+    pub fn fluid_kinds(&self) -> &[FluidId] {
+        &self.fluid_kind
+    }
+
+    pub fn has_fluid(&self) -> bool {
+        self.fluid_level.iter().any(|level| *level > 0)
+    }
+
+    // -- Rebuild a chunk from raw samples, None if they are the wrong size --
+    pub fn from_raw(
+        position: IVec3,
+        density: Vec<f32>,
+        material: Vec<MaterialId>,
+        fluid_level: Vec<u8>,
+        fluid_kind: Vec<FluidId>,
+    ) -> Option<Self> {
+        let len = (CHUNK_SAMPLES * CHUNK_SAMPLES * CHUNK_SAMPLES) as usize;
+        if [density.len(), material.len(), fluid_level.len(), fluid_kind.len()].iter().any(|l| *l != len) {
+            return None;
+        }
+
+        Some(Self { position, density, material, fluid_level, fluid_kind, edits: 0 })
+    }
+
+    pub fn dims() -> UVec3 {
+        UVec3::splat(CHUNK_SAMPLES as u32)
+    }
+
+    // -- Cells owned by this chunk, the rest of the grid is padding --
+    pub fn mesh_region() -> (UVec3, UVec3) {
+        let min = UVec3::splat(CHUNK_PADDING as u32);
+        (min, min + UVec3::splat(CHUNK_SIZE as u32))
+    }
+
+    // -- Mesh the owned cells, positions are relative to the chunk origin --
+    pub fn mesh(&self) -> mesher::MeshData {
+        let (min, max) = Self::mesh_region();
+        mesher::march_region(&self.density, Self::dims(), min, max, ISO_LEVEL)
+    }
+
+    // -- Changes every time a sample is written, meshes of an older value are stale --
+    pub fn edits(&self) -> u32 {
+        self.edits
+    }
+
+    // -- Same as mesh, but for the compute shader --
+    pub fn mesh_job(&self, entity: Entity) -> MarchingCubeJob {
+        let (min, max) = Self::mesh_region();
+
+        MarchingCubeJob {
+            entity,
+            edits: self.edits,
+            samples: Arc::new(self.density.clone()),
+            params: MarchingCubeParams::new(Self::dims(), min, max, ISO_LEVEL),
+        }
+    }
+}
 
-pub fn new(
-    mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-) {
+// -- Chunk coordinate that owns the given world sample --
+pub fn chunk_coord(world: IVec3) -> IVec3 {
+    IVec3::new(
+        world.x.div_euclid(CHUNK_SIZE),
+        world.y.div_euclid(CHUNK_SIZE),
+        world.z.div_euclid(CHUNK_SIZE),
+    )
+}
 
-}
\ No newline at end of file
+// -- World sample closest below the given position --
+pub fn world_to_voxel(position: Vec3) -> IVec3 {
+    position.floor().as_ivec3()
+}
@@ -1,83 +1,485 @@
 use bevy::{
+    core::cast_slice,
     prelude::*,
     render::{
         extract_resource::{ExtractResource, ExtractResourcePlugin},
-        render_asset::RenderAssets,
+        main_graph,
         render_graph::{self, RenderGraph},
         render_resource::*,
-        renderer::{RenderContext, RenderDevice},
+        renderer::{RenderContext, RenderDevice, RenderQueue},
         RenderApp, RenderStage,
     },
-    window::WindowDescriptor,
 };
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Mutex},
+};
 
 use crate::components::*;
+use super::{
+    marching_cube_table::TRIANGULATION_TABLE,
+    mesher::MeshData,
+};
+
+// -- Matches @workgroup_size in marching_cube.wgsl --
+const WORKGROUP_SIZE: u32 = 4;
+
+// -- Floats written per vertex, position then normal --
+const VERTEX_STRIDE: usize = 6;
+
+// -- Upper bound of triangles a single job can output. A 32³ chunk could in
+// theory need 5 per cell, 163840, but terrain stays far below one per cell on
+// average, and this keeps the output buffers of a job around 2.8 MB instead of 14.
+// Anything past it is dropped by the shader, and the job is meshed on the CPU --
+pub const MAX_TRIANGLES: u32 = 32768;
+
+// -- Sets of buffers kept around for the next jobs once read back --
+const MAX_POOLED_BUFFERS: usize = 8;
 
 pub struct ComputePlugin;
 
 impl Plugin for ComputePlugin {
     fn build(&self, app: &mut App) {
-        // Extract the game of life image resource from the main world into the render world
-        // for operation on by the compute shader and display on the sprite.
+        // -- Jobs go main world -> render world through extraction, the
+        // finished meshes come back through the shared output
+        let output = MarchingCubeOutput::default();
+
+        app.init_resource::<MarchingCubeJobs>();
+        app.insert_resource(output.clone());
+        app.add_plugin(ExtractResourcePlugin::<MarchingCubeJobs>::default());
 
         let render_app = app.sub_app_mut(RenderApp);
         render_app.init_resource::<MarchingCubePipeline>();
+        render_app.init_resource::<MarchingCubeJobs>();
+        render_app.init_resource::<MarchingCubeBatch>();
+        render_app.insert_resource(output);
+
+        render_app.add_system_to_stage(RenderStage::Prepare, prepare_jobs);
+        render_app.add_system_to_stage(RenderStage::Cleanup, read_back_jobs);
+
+        let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
+        render_graph.add_node("marching_cube", MarchingCubeNode::default());
+        render_graph
+            .add_node_edge("marching_cube", main_graph::node::CAMERA_DRIVER)
+            .unwrap();
+    }
+}
+
+// region: --Jobs--
+
+#[derive(Clone)]
+pub struct MarchingCubeJob {
+    pub entity: Entity,
+
+    // -- `Chunk::edits` when the job was queued, handed back with the mesh
+    pub edits: u32,
+
+    pub samples: Arc<Vec<f32>>,
+    pub params: MarchingCubeParams,
+}
+
+/// Grids queued for meshing this frame. Filled in the main world, it is
+/// cleared by whoever queues the next batch, and drained in the render world.
+#[derive(Default, Clone, ExtractResource)]
+pub struct MarchingCubeJobs(pub Vec<MarchingCubeJob>);
+
+/// Shared between both worlds, the render world pushes the finished meshes
+/// and the main world drains them, with the edit count of the job. A `None`
+/// mesh means the job had more triangles than the buffers could hold and has
+/// to be meshed on the CPU.
+#[derive(Default, Clone)]
+pub struct MarchingCubeOutput {
+    ready: Arc<AtomicBool>,
+    failed: Arc<AtomicBool>,
+    meshes: Arc<Mutex<Vec<(Entity, u32, Option<MeshData>)>>>,
+}
+
+impl MarchingCubeOutput {
+    // -- Has the compute pipeline finished compiling --
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+
+    // -- The pipeline failed to compile, everything is meshed on the CPU --
+    pub fn is_failed(&self) -> bool {
+        self.failed.load(Ordering::SeqCst)
+    }
+
+    pub fn drain(&self) -> Vec<(Entity, u32, Option<MeshData>)> {
+        std::mem::take(&mut *self.meshes.lock().unwrap())
+    }
+}
+
+#[derive(Default)]
+pub struct MarchingCubeBatch {
+    // -- Extracted but not uploaded yet, waiting on the pipeline
+    waiting: Vec<MarchingCubeJob>,
+
+    // -- Uploaded this frame, dispatched by the node
+    dispatched: Vec<(Entity, u32, MarchingCubeBuffers)>,
+
+    // -- Submitted and waiting on the staging buffers to map
+    in_flight: Vec<(Entity, u32, MarchingCubeBuffers)>,
+
+    // -- Read back and free to be uploaded to again
+    pool: Vec<MarchingCubeBuffers>,
+}
+
+fn prepare_jobs(
+    mut jobs: ResMut<MarchingCubeJobs>,
+    mut batch: ResMut<MarchingCubeBatch>,
+    output: Res<MarchingCubeOutput>,
+    pipeline: Res<MarchingCubePipeline>,
+    pipeline_cache: Res<PipelineCache>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    batch.waiting.append(&mut jobs.0);
+
+    if let CachedPipelineState::Err(_) = pipeline_cache.get_compute_pipeline_state(pipeline.pipeline) {
+        output.failed.store(true, Ordering::SeqCst);
+    }
+
+    if pipeline_cache.get_compute_pipeline(pipeline.pipeline).is_none() { return; }
+    output.ready.store(true, Ordering::SeqCst);
+
+    for job in std::mem::take(&mut batch.waiting) {
+        let pooled = batch.pool.iter().position(|buffers| buffers.fits(job.samples.len()));
+
+        let mut buffers = match pooled {
+            Some(index) => batch.pool.swap_remove(index),
+            None => MarchingCubeBuffers::new(&render_device, &render_queue, &pipeline, job.samples.len()),
+        };
+
+        buffers.upload(&render_device, &render_queue, &job.samples, job.params);
+        batch.dispatched.push((job.entity, job.edits, buffers));
+    }
+}
+
+// -- Runs after the frame was submitted, so the copies are queued by now --
+fn read_back_jobs(
+    mut batch: ResMut<MarchingCubeBatch>,
+    output: Res<MarchingCubeOutput>,
+    render_device: Res<RenderDevice>,
+) {
+    let batch = &mut *batch;
+    let mut meshes = output.meshes.lock().unwrap();
+
+    // -- Read whatever finished mapping since last frame
+    let (done, in_flight) = std::mem::take(&mut batch.in_flight)
+        .into_iter()
+        .partition::<Vec<_>, _>(|(_, _, buffers)| buffers.is_mapped());
+
+    batch.in_flight = in_flight;
+
+    for (entity, edits, buffers) in done {
+        let mesh = match buffers.read() {
+            Ok(data) => Some(data),
+            Err(triangles) => {
+                warn!(
+                    "{:?} needs {} triangles, more than the {} the GPU buffers hold, meshing it on the CPU",
+                    entity, triangles, MAX_TRIANGLES,
+                );
+                None
+            }
+        };
+
+        meshes.push((entity, edits, mesh));
+        if batch.pool.len() < MAX_POOLED_BUFFERS { batch.pool.push(buffers); }
+    }
+
+    for (entity, edits, buffers) in batch.dispatched.drain(..) {
+        buffers.map(&render_device);
+        batch.in_flight.push((entity, edits, buffers));
+    }
+}
+
+// endregion: --Jobs--
+
+#[derive(ShaderType, Clone, Default)]
+pub struct MarchingCubeParams {
+    pub dims: UVec3,
+    pub iso_level: f32,
+    pub min: UVec3,
+    pub max_triangles: u32,
+    pub max: UVec3,
+}
+
+impl MarchingCubeParams {
+    // -- Mesh the cells from min (inclusive) to max (exclusive) of a grid --
+    pub fn new(dims: UVec3, min: UVec3, max: UVec3, iso_level: f32) -> Self {
+        Self { dims, iso_level, min, max_triangles: MAX_TRIANGLES, max }
     }
 }
 
 pub struct MarchingCubePipeline {
-    // sim_pipeline: ComputePipeline,
-    init_pipeline: CachedComputePipelineId,
+    pub bind_group_layout: BindGroupLayout,
+    pub pipeline: CachedComputePipelineId,
+    pub triangulation: Buffer,
+}
+
+fn storage_entry(binding: u32, read_only: bool) -> BindGroupLayoutEntry {
+    BindGroupLayoutEntry {
+        binding,
+        visibility: ShaderStages::COMPUTE,
+        ty: BindingType::Buffer {
+            ty: BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
 }
 
 impl FromWorld for MarchingCubePipeline {
     fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
 
-        let bind_group_layout = world.resource::<RenderDevice>()
-        .create_bind_group_layout(&BindGroupLayoutDescriptor {
-            label: None,
-            entries: &[BindGroupLayoutEntry {
-                binding: 0,
-                visibility: ShaderStages::COMPUTE,
-                ty: BindingType::StorageTexture {
-                    access: StorageTextureAccess::ReadWrite,
-                    format: TextureFormat::Rgba8Unorm,
-                    view_dimension: TextureViewDimension::D2,
+        let bind_group_layout = render_device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("marching_cube_bind_group_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(MarchingCubeParams::min_size()),
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                storage_entry(1, true),     // densities
+                storage_entry(2, true),     // triangulation table
+                storage_entry(3, false),    // vertices
+                storage_entry(4, false),    // indices
+                storage_entry(5, false),    // triangle counter
+            ],
+        });
+
+        // -- The table never changes, so upload it once
+        let table: Vec<i32> = TRIANGULATION_TABLE.iter()
+            .flat_map(|row| row.iter().map(|edge| *edge as i32))
+            .collect();
+
+        let triangulation = render_device.create_buffer_with_data(&BufferInitDescriptor {
+            label: Some("marching_cube_triangulation"),
+            contents: cast_slice(&table),
+            usage: BufferUsages::STORAGE,
         });
 
         let shader = world.resource::<AssetServer>().load("./shaders/marching_cube.wgsl");
 
         let mut pipeline_cache = world.resource_mut::<PipelineCache>();
 
-        let init_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
-            label: None,
+        let pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+            label: Some(Cow::from("marching_cube_pipeline")),
             layout: Some(vec![bind_group_layout.clone()]),
-            shader: shader.clone(),
+            shader,
             shader_defs: vec![],
-            entry_point: Cow::from("init"),
+            entry_point: Cow::from("march"),
         });
 
-        // let update_pipeline = pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
-        //     label: None,
-        //     layout: Some(vec![texture_bind_group_layout.clone()]),
-        //     shader,
-        //     shader_defs: vec![],
-        //     entry_point: Cow::from("update"),
-        // });
-
         MarchingCubePipeline {
-            init_pipeline,
+            bind_group_layout,
+            pipeline,
+            triangulation,
         }
     }
 }
 
+/// GPU side resources for meshing one density grid at a time. Create it,
+/// `upload` a grid, `dispatch` and `copy_to_staging` in a command encoder,
+/// `map` once that has been submitted and `read` once `is_mapped` turns true.
+/// After the read it can be uploaded to again.
+pub struct MarchingCubeBuffers {
+    pub params: UniformBuffer<MarchingCubeParams>,
+    pub densities: Buffer,
+    pub vertices: Buffer,
+    pub indices: Buffer,
+    pub counter: Buffer,
+
+    pub staging_vertices: Buffer,
+    pub staging_indices: Buffer,
+    pub staging_counter: Buffer,
 
-const SIZE: (u32, u32) = (1280, 720);
-const WORKGROUP_SIZE: u32 = 8;
+    pub bind_group: BindGroup,
+    pub workgroups: UVec3,
+
+    // -- Number of samples the densities buffer has room for
+    capacity: usize,
+    mapped: Arc<AtomicUsize>,
+}
+
+impl MarchingCubeBuffers {
+    // -- Sized for grids of up to `capacity` samples and MAX_TRIANGLES of output --
+    pub fn new(
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        pipeline: &MarchingCubePipeline,
+        capacity: usize,
+    ) -> Self {
+        // -- Written once so the uniform has a buffer to bind, `upload` reuses it
+        let mut params = UniformBuffer::from(MarchingCubeParams::default());
+        params.write_buffer(render_device, render_queue);
+
+        let storage = |label, size, usage: BufferUsages| render_device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: BufferUsages::STORAGE | usage,
+            mapped_at_creation: false,
+        });
+
+        let staging = |label, size| render_device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            size,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let vertex_size = vertex_buffer_size();
+        let index_size = index_buffer_size();
+        let density_size = (capacity * std::mem::size_of::<f32>()) as u64;
+
+        let densities = storage("marching_cube_densities", density_size, BufferUsages::COPY_DST);
+        let vertices = storage("marching_cube_vertices", vertex_size, BufferUsages::COPY_SRC);
+        let indices = storage("marching_cube_indices", index_size, BufferUsages::COPY_SRC);
+        let counter = storage("marching_cube_counter", 4, BufferUsages::COPY_SRC | BufferUsages::COPY_DST);
+
+        let staging_vertices = staging("marching_cube_staging_vertices", vertex_size);
+        let staging_indices = staging("marching_cube_staging_indices", index_size);
+        let staging_counter = staging("marching_cube_staging_counter", 4);
+
+        let bind_group = render_device.create_bind_group(&BindGroupDescriptor {
+            label: Some("marching_cube_bind_group"),
+            layout: &pipeline.bind_group_layout,
+            entries: &[
+                BindGroupEntry { binding: 0, resource: params.binding().unwrap() },
+                BindGroupEntry { binding: 1, resource: densities.as_entire_binding() },
+                BindGroupEntry { binding: 2, resource: pipeline.triangulation.as_entire_binding() },
+                BindGroupEntry { binding: 3, resource: vertices.as_entire_binding() },
+                BindGroupEntry { binding: 4, resource: indices.as_entire_binding() },
+                BindGroupEntry { binding: 5, resource: counter.as_entire_binding() },
+            ],
+        });
+
+        Self {
+            params,
+            densities,
+            vertices,
+            indices,
+            counter,
+            staging_vertices,
+            staging_indices,
+            staging_counter,
+            bind_group,
+            workgroups: UVec3::ZERO,
+            capacity,
+            mapped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // -- Can a grid of this many samples be uploaded without growing the buffers --
+    pub fn fits(&self, samples: usize) -> bool {
+        samples <= self.capacity
+    }
+
+    // -- Queue the writes for the next job, only after the last one was read --
+    pub fn upload(
+        &mut self,
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        samples: &[f32],
+        params: MarchingCubeParams,
+    ) {
+        debug_assert!(self.fits(samples.len()));
+
+        // -- One invocation per cell, rounded up to whole workgroups
+        let cells = params.max - params.min;
+        self.workgroups = (cells + UVec3::splat(WORKGROUP_SIZE - 1)) / WORKGROUP_SIZE;
+
+        self.params.set(params);
+        self.params.write_buffer(render_device, render_queue);
+
+        render_queue.write_buffer(&self.densities, 0, cast_slice(samples));
+        render_queue.write_buffer(&self.counter, 0, cast_slice(&[0u32]));
+
+        self.mapped.store(0, Ordering::SeqCst);
+    }
+
+    pub fn dispatch<'a>(&'a self, pass: &mut ComputePass<'a>, pipeline: &'a ComputePipeline) {
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.dispatch_workgroups(self.workgroups.x, self.workgroups.y, self.workgroups.z);
+    }
+
+    pub fn copy_to_staging(&self, encoder: &mut CommandEncoder) {
+        encoder.copy_buffer_to_buffer(&self.vertices, 0, &self.staging_vertices, 0, vertex_buffer_size());
+        encoder.copy_buffer_to_buffer(&self.indices, 0, &self.staging_indices, 0, index_buffer_size());
+        encoder.copy_buffer_to_buffer(&self.counter, 0, &self.staging_counter, 0, 4);
+    }
+
+    // -- Request the staging buffers, only valid after the copy has been submitted --
+    pub fn map(&self, render_device: &RenderDevice) {
+        for buffer in [&self.staging_vertices, &self.staging_indices, &self.staging_counter] {
+            let mapped = self.mapped.clone();
+
+            render_device.map_buffer(&buffer.slice(..), MapMode::Read, move |result| {
+                if result.is_ok() { mapped.fetch_add(1, Ordering::SeqCst); }
+            });
+        }
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        self.mapped.load(Ordering::SeqCst) == 3
+    }
+
+    // -- Copy the output into a MeshData, or the number of triangles the
+    // shader needed if it ran out of room --
+    pub fn read(&self) -> Result<MeshData, u32> {
+        let triangles = {
+            let view = self.staging_counter.slice(..).get_mapped_range();
+            cast_slice::<u8, u32>(&view)[0]
+        };
+
+        let result = if triangles > MAX_TRIANGLES {
+            Err(triangles)
+        } else {
+            let vertex_count = triangles as usize * 3;
+            let mut data = MeshData::default();
+
+            {
+                let view = self.staging_vertices.slice(..).get_mapped_range();
+                let floats = cast_slice::<u8, f32>(&view);
+
+                for vertex in floats[..vertex_count * VERTEX_STRIDE].chunks(VERTEX_STRIDE) {
+                    data.positions.push([vertex[0], vertex[1], vertex[2]]);
+                    data.normals.push([vertex[3], vertex[4], vertex[5]]);
+                }
+            }
+
+            {
+                let view = self.staging_indices.slice(..).get_mapped_range();
+                data.indices.extend_from_slice(&cast_slice::<u8, u32>(&view)[..vertex_count]);
+            }
+
+            Ok(data)
+        };
+
+        self.staging_vertices.unmap();
+        self.staging_indices.unmap();
+        self.staging_counter.unmap();
+
+        result
+    }
+}
+
+fn vertex_buffer_size() -> u64 {
+    (MAX_TRIANGLES as usize * 3 * VERTEX_STRIDE * std::mem::size_of::<f32>()) as u64
+}
+
+fn index_buffer_size() -> u64 {
+    (MAX_TRIANGLES as usize * 3 * std::mem::size_of::<u32>()) as u64
+}
 
 impl render_graph::Node for MarchingCubeNode {
     fn update(&mut self, world: &mut World) {
@@ -88,13 +490,11 @@ impl render_graph::Node for MarchingCubeNode {
         match self.state {
             Initialized::Loading => {
                 if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.init_pipeline)
+                    pipeline_cache.get_compute_pipeline_state(pipeline.pipeline)
                 { self.state = Initialized::Init; }
             }
             Initialized::Init => {
-                if let CachedPipelineState::Ok(_) =
-                    pipeline_cache.get_compute_pipeline_state(pipeline.init_pipeline)
-                { self.state = Initialized::Update; }
+                self.state = Initialized::Update;
             }
             Initialized::Update => {}
         }
@@ -106,26 +506,208 @@ impl render_graph::Node for MarchingCubeNode {
         render_context: &mut RenderContext,
         world: &World,
     ) -> Result<(), render_graph::NodeRunError> {
-
         let pipeline_cache = world.resource::<PipelineCache>();
         let pipeline = world.resource::<MarchingCubePipeline>();
+        let batch = world.resource::<MarchingCubeBatch>();
 
-        let mut pass = render_context.command_encoder
-            .begin_compute_pass(&ComputePassDescriptor::default());
+        if batch.dispatched.is_empty() { return Ok(()); }
 
-        // select the pipeline based on the current state
-        match self.state {
-            Initialized::Loading => {}
-            Initialized::Init => {
-                let init_pipeline = pipeline_cache
-                    .get_compute_pipeline(pipeline.init_pipeline)
-                    .unwrap();
-                pass.set_pipeline(init_pipeline);
-                pass.dispatch_workgroups(SIZE.0 / WORKGROUP_SIZE, SIZE.1 / WORKGROUP_SIZE, 1);
+        // -- prepare_jobs only uploads once the pipeline exists
+        let compute_pipeline = match pipeline_cache.get_compute_pipeline(pipeline.pipeline) {
+            Some(compute_pipeline) => compute_pipeline,
+            None => return Ok(()),
+        };
+
+        {
+            let mut pass = render_context.command_encoder
+                .begin_compute_pass(&ComputePassDescriptor { label: Some("marching_cube_pass") });
+
+            for (_, _, buffers) in batch.dispatched.iter() {
+                buffers.dispatch(&mut pass, compute_pipeline);
             }
-            Initialized::Update => {}
+        }
+
+        for (_, _, buffers) in batch.dispatched.iter() {
+            buffers.copy_to_staging(&mut render_context.command_encoder);
         }
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_engine::chunk::mesher;
+    use wgpu::util::DeviceExt;
+
+    const SHADER: &str = include_str!("../../../assets/shaders/marching_cube.wgsl");
+
+    // -- Params as laid out in the uniform, vec3s are aligned to 16 bytes --
+    fn params_bytes(dims: UVec3, min: UVec3, max: UVec3, iso_level: f32) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(48);
+
+        for value in dims.to_array() { bytes.extend_from_slice(&value.to_le_bytes()); }
+        bytes.extend_from_slice(&iso_level.to_le_bytes());
+        for value in min.to_array() { bytes.extend_from_slice(&value.to_le_bytes()); }
+        bytes.extend_from_slice(&MAX_TRIANGLES.to_le_bytes());
+        for value in max.to_array() { bytes.extend_from_slice(&value.to_le_bytes()); }
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+
+        bytes
+    }
+
+    // -- Mesh on a software adapter --
+    fn march_on_gpu(samples: &[f32], dims: UVec3, min: UVec3, max: UVec3, iso_level: f32) -> MeshData {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: true,
+            compatible_surface: None,
+        })).expect("no software adapter available");
+
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: adapter.limits(),
+        }, None)).expect("the software adapter did not give a device");
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER)),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: None,
+            module: &module,
+            entry_point: "march",
+        });
+
+        let table: Vec<i32> = TRIANGULATION_TABLE.iter()
+            .flat_map(|row| row.iter().map(|edge| *edge as i32))
+            .collect();
+
+        let init = |contents: &[u8], usage| device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents,
+            usage,
+        });
+        let empty = |size, usage| device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage,
+            mapped_at_creation: false,
+        });
+
+        let storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC;
+        let staging = wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST;
+
+        let params = init(&params_bytes(dims, min, max, iso_level), wgpu::BufferUsages::UNIFORM);
+        let densities = init(cast_slice(samples), wgpu::BufferUsages::STORAGE);
+        let triangulation = init(cast_slice(&table), wgpu::BufferUsages::STORAGE);
+        let vertices = empty(vertex_buffer_size(), storage);
+        let indices = empty(index_buffer_size(), storage);
+        let counter = init(cast_slice(&[0u32]), storage);
+
+        let staging_vertices = empty(vertex_buffer_size(), staging);
+        let staging_counter = empty(4, staging);
+
+        let buffers = [&params, &densities, &triangulation, &vertices, &indices, &counter];
+        let entries: Vec<wgpu::BindGroupEntry> = buffers.iter()
+            .enumerate()
+            .map(|(binding, buffer)| wgpu::BindGroupEntry { binding: binding as u32, resource: buffer.as_entire_binding() })
+            .collect();
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &entries,
+        });
+
+        let workgroups = (max - min + UVec3::splat(WORKGROUP_SIZE - 1)) / WORKGROUP_SIZE;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+        }
+
+        encoder.copy_buffer_to_buffer(&vertices, 0, &staging_vertices, 0, vertex_buffer_size());
+        encoder.copy_buffer_to_buffer(&counter, 0, &staging_counter, 0, 4);
+        queue.submit(Some(encoder.finish()));
+
+        staging_vertices.slice(..).map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        staging_counter.slice(..).map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+
+        let triangles = cast_slice::<u8, u32>(&staging_counter.slice(..).get_mapped_range())[0];
+        assert!(triangles <= MAX_TRIANGLES, "test grid overflowed the GPU buffers");
+
+        let view = staging_vertices.slice(..).get_mapped_range();
+        let floats = cast_slice::<u8, f32>(&view);
+        let mut data = MeshData::default();
+
+        for vertex in floats[..triangles as usize * 3 * VERTEX_STRIDE].chunks(VERTEX_STRIDE) {
+            data.positions.push([vertex[0], vertex[1], vertex[2]]);
+            data.normals.push([vertex[3], vertex[4], vertex[5]]);
+        }
+
+        data
+    }
+
+    fn close(a: &[f32; 3], b: &[f32; 3], tolerance: f32) -> bool {
+        a.iter().zip(b.iter()).all(|(a, b)| (a - b).abs() <= tolerance)
+    }
+
+    #[test]
+    #[ignore = "needs a wgpu software adapter, run with `cargo test -- --ignored`"]
+    fn gpu_matches_cpu() {
+        // -- A lumpy ball in a padded grid, only the inner cells are meshed like a chunk
+        let dims = UVec3::splat(12);
+        let center = Vec3::new(5.6, 5.3, 5.1);
+
+        let mut samples = Vec::new();
+        for z in 0..dims.z {
+            for y in 0..dims.y {
+                for x in 0..dims.x {
+                    let p = Vec3::new(x as f32, y as f32, z as f32);
+                    samples.push(4.2 - p.distance(center) + (p.x * 0.7).sin() * 0.4);
+                }
+            }
+        }
+
+        let (min, max) = (UVec3::ONE, dims - UVec3::splat(2));
+        let cpu = mesher::march_region(&samples, dims, min, max, 0.0);
+
+        let gpu = march_on_gpu(&samples, dims, min, max, 0.0);
+
+        assert!(!cpu.is_empty());
+        assert_eq!(gpu.triangle_count(), cpu.triangle_count());
+
+        // -- Invocations finish in any order, so match every CPU triangle to a GPU one
+        let triangle = |data: &MeshData, i: usize| -> Vec<([f32; 3], [f32; 3])> {
+            (0..3).map(|j| (data.positions[i * 3 + j], data.normals[i * 3 + j])).collect()
+        };
+
+        let mut used = vec![false; gpu.triangle_count()];
+
+        for i in 0..cpu.triangle_count() {
+            let expected = triangle(&cpu, i);
+
+            let found = (0..gpu.triangle_count()).find(|&g| {
+                !used[g] && triangle(&gpu, g).iter().zip(expected.iter()).all(|((position, normal), (cpu_position, cpu_normal))| {
+                    close(position, cpu_position, 1e-4) && close(normal, cpu_normal, 1e-3)
+                })
+            });
+
+            match found {
+                Some(g) => used[g] = true,
+                None => panic!("no GPU triangle matches CPU triangle {}: {:?}", i, expected),
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use bevy::{
+    math::{UVec3, Vec3},
+    render::{mesh::{Indices, Mesh}, render_resource::PrimitiveTopology},
+};
+
+use super::marching_cube_table::{CORNER_INDEX, TRIANGULATION_TABLE};
+
+// -- Offsets of the 8 corners of a cell, in the order the
+// triangulation table expects them (y is up) --
+pub const CORNER_OFFSETS: [[u32; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [1, 0, 1],
+    [0, 0, 1],
+    [0, 1, 0],
+    [1, 1, 0],
+    [1, 1, 1],
+    [0, 1, 1],
+];
+
+/// Raw triangle data produced by the CPU mesher, laid out the same way
+/// the marching cube compute shader writes its output buffers.
+#[derive(Default, Clone, Debug)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn into_mesh(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+        mesh
+    }
+}
+
+// -- Flattened index of a sample in a grid of the given dimensions --
+pub fn sample_index(dims: UVec3, x: u32, y: u32, z: u32) -> usize {
+    (x + y * dims.x + z * dims.x * dims.y) as usize
+}
+
+/// Meshes every cell of a density grid. Samples are stored x first, then y,
+/// then z, and a sample is considered solid when it is above `iso_level`.
+pub fn march(samples: &[f32], dims: UVec3, iso_level: f32) -> MeshData {
+    march_region(samples, dims, UVec3::ZERO, dims - UVec3::ONE, iso_level)
+}
+
+/// Meshes the cells from `min` (inclusive) to `max` (exclusive), with the
+/// resulting positions relative to `min`. Samples outside the region are only
+/// used for normals, which is how padded chunks get seamless lighting.
+pub fn march_region(
+    samples: &[f32],
+    dims: UVec3,
+    min: UVec3,
+    max: UVec3,
+    iso_level: f32,
+) -> MeshData {
+    assert_eq!(samples.len(), (dims.x * dims.y * dims.z) as usize);
+    assert!(max.cmple(dims - UVec3::ONE).all(), "region is outside the grid");
+
+    let mut data = MeshData::default();
+
+    for z in min.z..max.z {
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                march_cell(samples, dims, UVec3::new(x, y, z), min, iso_level, &mut data);
+            }
+        }
+    }
+
+    data
+}
+
+fn march_cell(
+    samples: &[f32],
+    dims: UVec3,
+    cell: UVec3,
+    origin: UVec3,
+    iso_level: f32,
+    data: &mut MeshData,
+) {
+    // -- Gather the corners of the cell
+    let mut corners = [UVec3::ZERO; 8];
+    let mut values = [0.0; 8];
+    let mut cube_index = 0;
+
+    for (i, offset) in CORNER_OFFSETS.iter().enumerate() {
+        corners[i] = cell + UVec3::from(*offset);
+        values[i] = samples[sample_index(dims, corners[i].x, corners[i].y, corners[i].z)];
+
+        if values[i] > iso_level { cube_index |= 1 << i; }
+    }
+
+    // -- Fully solid or fully empty cells produce nothing
+    let triangulation = &TRIANGULATION_TABLE[cube_index];
+
+    for triangle in triangulation.chunks(3) {
+        if triangle[0] < 0 { break; }
+
+        let base = data.positions.len() as u32;
+
+        for edge in triangle.iter() {
+            let [a, b] = CORNER_INDEX[*edge as usize];
+            let (a, b) = (a as usize, b as usize);
+
+            // -- Interpolate along the edge to where the surface crosses it
+            let t = interpolation_factor(values[a], values[b], iso_level);
+
+            let position = corners[a].as_vec3().lerp(corners[b].as_vec3(), t) - origin.as_vec3();
+            let normal = gradient(samples, dims, corners[a])
+                .lerp(gradient(samples, dims, corners[b]), t);
+
+            data.positions.push(position.to_array());
+            data.normals.push((-normal).normalize_or_zero().to_array());
+        }
+
+        data.indices.extend_from_slice(&[base, base + 1, base + 2]);
+    }
+}
+
+fn interpolation_factor(a: f32, b: f32, iso_level: f32) -> f32 {
+    if (b - a).abs() < f32::EPSILON { return 0.5; }
+    ((iso_level - a) / (b - a)).clamp(0.0, 1.0)
+}
+
+// -- Central difference of the density field, falling back to a one sided
+// difference on the borders of the grid --
+fn gradient(samples: &[f32], dims: UVec3, p: UVec3) -> Vec3 {
+    let sample = |x: u32, y: u32, z: u32| samples[sample_index(dims, x, y, z)];
+
+    let axis = |value: u32, max: u32| -> (u32, u32) {
+        (value.saturating_sub(1), (value + 1).min(max - 1))
+    };
+
+    let (x0, x1) = axis(p.x, dims.x);
+    let (y0, y1) = axis(p.y, dims.y);
+    let (z0, z1) = axis(p.z, dims.z);
+
+    Vec3::new(
+        (sample(x1, p.y, p.z) - sample(x0, p.y, p.z)) / (x1 - x0).max(1) as f32,
+        (sample(p.x, y1, p.z) - sample(p.x, y0, p.z)) / (y1 - y0).max(1) as f32,
+        (sample(p.x, p.y, z1) - sample(p.x, p.y, z0)) / (z1 - z0).max(1) as f32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(dims: UVec3, density: impl Fn(UVec3) -> f32) -> Vec<f32> {
+        let mut samples = Vec::with_capacity((dims.x * dims.y * dims.z) as usize);
+
+        for z in 0..dims.z {
+            for y in 0..dims.y {
+                for x in 0..dims.x {
+                    samples.push(density(UVec3::new(x, y, z)));
+                }
+            }
+        }
+
+        samples
+    }
+
+    #[test]
+    fn flat_plane_faces_up() {
+        let dims = UVec3::new(5, 3, 4);
+        let samples = grid(dims, |p| 0.5 - p.y as f32);
+
+        let data = march(&samples, dims, 0.0);
+
+        // -- Two triangles for every cell the plane passes through
+        assert_eq!(data.triangle_count(), 2 * 4 * 3);
+
+        for (position, normal) in data.positions.iter().zip(data.normals.iter()) {
+            assert!((position[1] - 0.5).abs() < 1e-5, "vertex off the plane: {:?}", position);
+            assert!(normal[1] > 0.99, "normal not pointing up: {:?}", normal);
+        }
+    }
+
+    #[test]
+    fn uniform_grids_are_empty() {
+        let dims = UVec3::splat(4);
+
+        assert!(march(&grid(dims, |_| 1.0), dims, 0.0).is_empty());
+        assert!(march(&grid(dims, |_| -1.0), dims, 0.0).is_empty());
+    }
+
+    #[test]
+    fn single_corner_is_one_triangle() {
+        let dims = UVec3::splat(2);
+        let samples = grid(dims, |p| if p == UVec3::ZERO { 1.0 } else { -1.0 });
+
+        let data = march(&samples, dims, 0.0);
+
+        assert_eq!(data.triangle_count(), 1);
+        assert_eq!(data.positions.len(), 3);
+
+        // -- Every vertex sits halfway along an edge out of the corner
+        for position in data.positions.iter() {
+            let sum: f32 = position.iter().sum();
+            assert!((sum - 0.5).abs() < 1e-5, "vertex not on a corner edge: {:?}", position);
+        }
+    }
+
+    #[test]
+    fn region_is_relative_to_its_min() {
+        let dims = UVec3::new(4, 4, 4);
+        let samples = grid(dims, |p| 1.5 - p.y as f32);
+
+        let data = march_region(&samples, dims, UVec3::ONE, UVec3::new(3, 3, 3), 0.0);
+
+        assert_eq!(data.triangle_count(), 2 * 2 * 2);
+        for position in data.positions.iter() {
+            assert!((position[1] - 0.5).abs() < 1e-5);
+        }
+    }
+}
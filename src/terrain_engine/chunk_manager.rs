@@ -0,0 +1,280 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::{Collider, RigidBody};
+
+use crate::components::Player;
+use super::{
+    chunk::{
+        self,
+        marching_cube::{MarchingCubeJobs, MarchingCubeOutput},
+        mesher::MeshData,
+        Chunk, Voxel,
+    },
+    fluid::Fluid,
+    generator::TerrainGenerator,
+    material,
+    world_save::WorldSave,
+};
+
+pub struct ChunkManager {
+    // -- Radius in chunks around the player that is kept loaded
+    pub view_radius: i32,
+    pub vertical_radius: i32,
+
+    // -- Caps to spread the work over multiple frames
+    pub loads_per_frame: usize,
+    pub meshes_per_frame: usize,
+
+    // -- Mesh on the GPU once the compute pipeline is ready, CPU until then
+    pub gpu_meshing: bool,
+
+    pub chunks: HashMap<IVec3, Entity>,
+    pub material: Handle<StandardMaterial>,
+}
+
+impl Default for ChunkManager {
+    fn default() -> Self {
+        Self {
+            view_radius: 6,
+            vertical_radius: 2,
+            loads_per_frame: 8,
+            meshes_per_frame: 4,
+            gpu_meshing: true,
+            chunks: HashMap::default(),
+            material: Handle::default(),
+        }
+    }
+}
+
+impl ChunkManager {
+    pub fn get(&self, position: IVec3) -> Option<Entity> {
+        self.chunks.get(&position).copied()
+    }
+
+    // -- Read a world sample from the chunk that owns it, None if it is not loaded --
+    pub fn voxel(&self, chunks: &Query<&Chunk>, world: IVec3) -> Option<Voxel> {
+        let entity = self.get(chunk::chunk_coord(world))?;
+        chunks.get(entity).ok()?.get_world(world)
+    }
+
+    // -- Same as voxel, for the fluid in the sample --
+    pub fn fluid(&self, chunks: &Query<&Chunk>, world: IVec3) -> Option<Fluid> {
+        let entity = self.get(chunk::chunk_coord(world))?;
+        chunks.get(entity).ok()?.fluid_world(world)
+    }
+
+    // -- Is the chunk within the view radius of the given center chunk --
+    pub fn in_range(&self, center: IVec3, position: IVec3, margin: i32) -> bool {
+        let offset = position - center;
+        let radius = self.view_radius + margin;
+
+        offset.x * offset.x + offset.z * offset.z <= radius * radius
+            && offset.y.abs() <= self.vertical_radius + margin
+    }
+}
+
+// -- Marks a chunk whose density changed and needs a new mesh --
+#[derive(Component, Default, Clone, Debug)]
+pub struct ChunkDirty;
+
+// -- The fluid in the chunk changed and needs new fluid meshes --
+#[derive(Component, Default, Clone, Debug)]
+pub struct FluidDirty;
+
+// -- The chunk was edited since it was last saved --
+#[derive(Component, Default, Clone, Debug)]
+pub struct ChunkModified;
+
+// -- The chunk has had at least one mesh built for it --
+#[derive(Component, Default, Clone, Debug)]
+pub struct ChunkMeshed;
+
+pub fn setup(
+    mut manager: ResMut<ChunkManager>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    manager.material = materials.add(StandardMaterial {
+        base_color: material::color(material::GRASS),
+        perceptual_roughness: 0.9,
+        ..default()
+    });
+}
+
+// -- Chunk the player is currently standing in --
+pub fn player_chunk(player: &Query<&Transform, With<Player>>) -> Option<IVec3> {
+    player.iter().next().map(|transform| {
+        chunk::chunk_coord(chunk::world_to_voxel(transform.translation))
+    })
+}
+
+pub fn load_chunks(
+    mut commands: Commands,
+    mut manager: ResMut<ChunkManager>,
+    mut save: ResMut<WorldSave>,
+    generator: Res<TerrainGenerator>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let center = match player_chunk(&player) {
+        Some(center) => center,
+        None => return,
+    };
+
+    // -- Collect every missing chunk in range, closest first
+    let radius = manager.view_radius;
+    let vertical = manager.vertical_radius;
+    let mut missing = Vec::new();
+
+    for y in -vertical..=vertical {
+        for z in -radius..=radius {
+            for x in -radius..=radius {
+                let position = center + IVec3::new(x, y, z);
+
+                if manager.in_range(center, position, 0) && !manager.chunks.contains_key(&position) {
+                    missing.push(position);
+                }
+            }
+        }
+    }
+
+    missing.sort_by_key(|position| (*position - center).abs().max_element());
+    missing.truncate(manager.loads_per_frame);
+
+    for position in missing {
+        // -- Edited chunks come from the save, the rest is generated
+        let chunk = save.load_chunk(position).unwrap_or_else(|| {
+            let mut chunk = Chunk::new(position);
+            generator.fill_chunk(&mut chunk);
+            chunk
+        });
+
+        let has_fluid = chunk.has_fluid();
+
+        let entity = commands.spawn_bundle(PbrBundle {
+            transform: Transform::from_translation(chunk.origin().as_vec3()),
+            material: manager.material.clone(),
+            ..default()
+        })
+        .insert(chunk)
+        .insert(RigidBody::Fixed)
+        .insert(ChunkDirty)
+        .id();
+
+        if has_fluid { commands.entity(entity).insert(FluidDirty); }
+
+        manager.chunks.insert(position, entity);
+    }
+}
+
+pub fn unload_chunks(
+    mut commands: Commands,
+    mut manager: ResMut<ChunkManager>,
+    mut save: ResMut<WorldSave>,
+    modified: Query<&Chunk, With<ChunkModified>>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let center = match player_chunk(&player) {
+        Some(center) => center,
+        None => return,
+    };
+
+    // -- One chunk of slack so walking along a border does not thrash
+    let out_of_range: Vec<IVec3> = manager.chunks.keys()
+        .filter(|position| !manager.in_range(center, **position, 1))
+        .copied()
+        .collect();
+
+    if out_of_range.is_empty() { return; }
+
+    for position in out_of_range {
+        if let Some(entity) = manager.chunks.remove(&position) {
+            // -- Keep the edits, they reach the disk on the next save
+            if let Ok(chunk) = modified.get(entity) { save.store_chunk(chunk); }
+
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    save.release_regions(manager.chunks.keys().copied());
+}
+
+pub fn mesh_chunks(
+    mut commands: Commands,
+    manager: Res<ChunkManager>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut jobs: ResMut<MarchingCubeJobs>,
+    output: Res<MarchingCubeOutput>,
+    chunks: Query<(Entity, &Chunk), With<ChunkDirty>>,
+    player: Query<&Transform, With<Player>>,
+) {
+    // -- Last frames jobs have been extracted by now
+    if !jobs.0.is_empty() { jobs.0.clear(); }
+
+    let center = player_chunk(&player).unwrap_or_default();
+    let gpu = manager.gpu_meshing && output.is_ready();
+
+    // -- Mesh the closest dirty chunks first
+    let mut dirty: Vec<(Entity, &Chunk)> = chunks.iter().collect();
+    dirty.sort_by_key(|(_, chunk)| (chunk.position - center).abs().max_element());
+
+    for (entity, chunk) in dirty.into_iter().take(manager.meshes_per_frame) {
+        if gpu {
+            jobs.0.push(chunk.mesh_job(entity));
+        } else {
+            insert_mesh(&mut commands, &mut meshes, entity, chunk.mesh());
+        }
+
+        commands.entity(entity).remove::<ChunkDirty>();
+    }
+}
+
+// -- Pick up the meshes the compute shader finished --
+pub fn receive_meshes(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    output: Res<MarchingCubeOutput>,
+    chunks: Query<&Chunk>,
+) {
+    for (entity, edits, data) in output.drain() {
+        // -- The chunk might have been unloaded while it was meshing
+        let chunk = match chunks.get(entity) {
+            Ok(chunk) => chunk,
+            Err(_) => continue,
+        };
+
+        // -- Edited again since the job was queued, the newer mesh is already
+        // in or still to come, so this one would only put the old shape back
+        if chunk.edits() != edits { continue; }
+
+        // -- Too many triangles for the GPU buffers, fall back to the CPU
+        let data = data.unwrap_or_else(|| chunk.mesh());
+
+        insert_mesh(&mut commands, &mut meshes, entity, data);
+    }
+}
+
+// -- Swap in a new mesh and the matching trimesh collider --
+pub fn insert_mesh(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    entity: Entity,
+    data: MeshData,
+) {
+    let mut entity = commands.entity(entity);
+
+    // -- Rapier does not accept a trimesh without triangles
+    match collider(&data) {
+        Some(collider) => { entity.insert(collider); },
+        None => { entity.remove::<Collider>(); },
+    }
+
+    entity.insert(meshes.add(data.into_mesh()));
+    entity.insert(ChunkMeshed);
+}
+
+pub fn collider(data: &MeshData) -> Option<Collider> {
+    if data.is_empty() { return None; }
+
+    let vertices = data.positions.iter().map(|position| Vec3::from(*position)).collect();
+    let indices = data.indices.chunks(3).map(|i| [i[0], i[1], i[2]]).collect();
+
+    Some(Collider::trimesh(vertices, indices))
+}
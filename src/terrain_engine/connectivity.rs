@@ -0,0 +1,269 @@
+use bevy::{prelude::*, utils::HashSet};
+use std::collections::VecDeque;
+
+use super::{
+    chunk::{Chunk, Voxel},
+    chunk_manager::ChunkManager,
+    edit::{write_samples, BrushMode},
+    material::AIR,
+    stress::StressAnalysis,
+    voxel_body::{self, VoxelBody},
+};
+
+const NEIGHBOURS: [IVec3; 6] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z];
+
+/// Areas around edits waiting to be checked for pieces of terrain that are no
+/// longer attached to anything. Only solid samples inside the search box are
+/// flood filled, anything touching the edge of the box, below the bedrock
+/// level or in an unloaded chunk counts as anchored. This is the only queue
+/// edits go into, each area is handed on to `StressAnalysis` once checked.
+pub struct Connectivity {
+    // -- Samples past the edit bounds to search, the biggest island that can fall off
+    pub search_margin: i32,
+    pub bedrock_level: i32,
+
+    // -- Islands smaller than this crumble away instead of becoming a body
+    pub min_island_samples: usize,
+
+    pub checks_per_frame: usize,
+
+    // -- Edited samples, min and max inclusive, without the margin, and
+    // whether the edit could have cut anything off
+    pub pending: VecDeque<(IVec3, IVec3, bool)>,
+}
+
+impl Default for Connectivity {
+    fn default() -> Self {
+        Self {
+            search_margin: 12,
+            bedrock_level: -64,
+            min_island_samples: 8,
+            checks_per_frame: 2,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl Connectivity {
+    // -- Queue a check around the samples from min to max, inclusive. Filling
+    // only adds terrain, so nothing can come loose and only stress is checked --
+    pub fn check(&mut self, min: IVec3, max: IVec3, mode: BrushMode) {
+        self.pending.push_back((min, max, mode != BrushMode::Fill));
+    }
+}
+
+/// Flood fills the solid samples between `min` and `max` (inclusive) from every
+/// anchored one, and returns each group of solid samples that was not reached.
+/// Samples are visited in a fixed order so the result is always the same.
+pub fn find_islands(
+    min: IVec3,
+    max: IVec3,
+    bedrock_level: i32,
+    sample: impl Fn(IVec3) -> Option<Voxel>,
+) -> Vec<Vec<IVec3>> {
+    let size = max - min + IVec3::ONE;
+    if size.min_element() <= 0 { return Vec::new(); }
+
+    let index = |p: IVec3| {
+        let local = p - min;
+        (local.x + local.y * size.x + local.z * size.x * size.y) as usize
+    };
+    let inside = |p: IVec3| p.cmpge(min).all() && p.cmple(max).all();
+
+    let len = (size.x * size.y * size.z) as usize;
+    let mut solid = vec![false; len];
+    let mut visited = vec![false; len];
+    let mut queue = VecDeque::new();
+
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let p = IVec3::new(x, y, z);
+                let voxel = sample(p);
+
+                // -- Unloaded samples are treated as solid ground
+                if !voxel.map_or(true, |voxel| voxel.is_solid()) { continue; }
+
+                solid[index(p)] = true;
+
+                let edge = p.cmpeq(min).any() || p.cmpeq(max).any();
+
+                if edge || voxel.is_none() || p.y <= bedrock_level {
+                    visited[index(p)] = true;
+                    queue.push_back(p);
+                }
+            }
+        }
+    }
+
+    let fill = |queue: &mut VecDeque<IVec3>, visited: &mut [bool], found: &mut Vec<IVec3>| {
+        while let Some(p) = queue.pop_front() {
+            found.push(p);
+
+            for direction in NEIGHBOURS {
+                let next = p + direction;
+                if !inside(next) { continue; }
+
+                let i = index(next);
+                if solid[i] && !visited[i] {
+                    visited[i] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+    };
+
+    // -- Everything the anchors reach stays put
+    fill(&mut queue, &mut visited, &mut Vec::new());
+
+    let mut islands = Vec::new();
+
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let p = IVec3::new(x, y, z);
+                let i = index(p);
+                if !solid[i] || visited[i] { continue; }
+
+                visited[i] = true;
+                queue.push_back(p);
+
+                let mut island = Vec::new();
+                fill(&mut queue, &mut visited, &mut island);
+                islands.push(island);
+            }
+        }
+    }
+
+    islands
+}
+
+/// The samples of an island copied into a body of their own, with a border of
+/// air so the surface closes. Air around the island keeps its density so the
+/// cut out piece has the same shape it had in the terrain. Returns the world
+/// position of the bodies (0, 0, 0) sample with it.
+pub fn island_body(island: &[IVec3], sample: impl Fn(IVec3) -> Option<Voxel>) -> (IVec3, VoxelBody) {
+    let mut min = island[0];
+    let mut max = island[0];
+
+    for p in island.iter() {
+        min = min.min(*p);
+        max = max.max(*p);
+    }
+
+    let origin = min - IVec3::ONE;
+    let dims = (max - min + IVec3::splat(3)).as_uvec3();
+    let members: HashSet<IVec3> = island.iter().copied().collect();
+
+    let mut voxels = Vec::with_capacity((dims.x * dims.y * dims.z) as usize);
+
+    for z in 0..dims.z as i32 {
+        for y in 0..dims.y as i32 {
+            for x in 0..dims.x as i32 {
+                let world = origin + IVec3::new(x, y, z);
+                let voxel = sample(world).unwrap_or_else(Voxel::air);
+
+                voxels.push(if members.contains(&world) {
+                    voxel
+                } else if !voxel.is_solid() {
+                    Voxel { density: voxel.density, material: AIR }
+                } else {
+                    Voxel::air()
+                });
+            }
+        }
+    }
+
+    (origin, VoxelBody::from_voxels(dims, voxels))
+}
+
+// -- Cut every detached island out of the terrain and drop it as a body, then
+// pass the area on to be checked for overhangs --
+pub fn detach_islands(
+    mut commands: Commands,
+    mut connectivity: ResMut<Connectivity>,
+    mut stress: ResMut<StressAnalysis>,
+    manager: Res<ChunkManager>,
+    mut chunks: ParamSet<(Query<&Chunk>, Query<&mut Chunk>)>,
+) {
+    for _ in 0..connectivity.checks_per_frame {
+        let (from, to, search) = match connectivity.pending.pop_front() {
+            Some(check) => check,
+            None => return,
+        };
+
+        if !search {
+            stress.pending.push_back((from, to));
+            continue;
+        }
+
+        let margin = IVec3::splat(connectivity.search_margin);
+        let (min, max) = (from - margin, to + margin);
+
+        let (islands, bodies) = {
+            let read = chunks.p0();
+            let sample = |world| manager.voxel(&read, world);
+
+            let islands = find_islands(min, max, connectivity.bedrock_level, &sample);
+            let bodies: Vec<(IVec3, VoxelBody)> = islands.iter()
+                .filter(|island| island.len() >= connectivity.min_island_samples)
+                .map(|island| island_body(island, &sample))
+                .collect();
+
+            (islands, bodies)
+        };
+
+        // -- Remove the islands from the terrain, small ones are simply gone
+        let changed: Vec<(IVec3, Voxel)> = islands.iter()
+            .flatten()
+            .map(|world| (*world, Voxel::air()))
+            .collect();
+
+        write_samples(&mut commands, &manager, &mut chunks.p1(), &changed);
+
+        for (origin, body) in bodies {
+            voxel_body::spawn(
+                &mut commands,
+                body,
+                Transform::from_translation(origin.as_vec3()),
+                manager.material.clone(),
+            );
+        }
+
+        // -- After the islands are gone, so they are not also counted as failed overhangs
+        stress.pending.push_back((from, to));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_engine::material::STONE;
+
+    const MIN: IVec3 = IVec3::splat(-4);
+    const MAX: IVec3 = IVec3::splat(4);
+
+    // -- Solid wherever `solid` says so, air everywhere else --
+    fn islands(solid: impl Fn(IVec3) -> bool) -> Vec<Vec<IVec3>> {
+        find_islands(MIN, MAX, -100, |p| Some(if solid(p) { Voxel { density: 1.0, material: STONE } } else { Voxel::air() }))
+    }
+
+    #[test]
+    fn column_on_the_ground_is_anchored() {
+        assert!(islands(|p| p.x == 0 && p.z == 0 && p.y <= 2).is_empty());
+    }
+
+    #[test]
+    fn floating_block_is_an_island() {
+        let found = islands(|p| p.cmpge(IVec3::ZERO).all() && p.cmple(IVec3::ONE).all());
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].len(), 8);
+        assert_eq!(found[0][0], IVec3::ZERO);
+    }
+
+    #[test]
+    fn block_on_the_edge_of_the_box_is_anchored() {
+        assert!(islands(|p| p.x >= 3 && p.cmpge(IVec3::ZERO).all() && p.cmple(IVec3::new(4, 1, 1)).all()).is_empty());
+    }
+}
@@ -0,0 +1,253 @@
+use bevy::prelude::*;
+
+use super::{
+    chunk::{self, Chunk, Voxel, ISO_LEVEL},
+    chunk_manager::{ChunkDirty, ChunkManager, ChunkModified, FluidDirty},
+    connectivity::Connectivity,
+    fluid::Fluid,
+    material::{MaterialId, STONE},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrushShape {
+    Sphere,
+    Cube,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrushMode {
+    // -- Remove everything inside the brush
+    Dig,
+
+    // -- Make everything inside the brush solid
+    Fill,
+
+    // -- Blend the density towards the average of its neighbours,
+    // strongest in the center of the brush
+    Smooth,
+}
+
+/// Send this event to change the terrain. Every chunk that stores one of the
+/// touched samples, neighbours' padding included, gets marked for remeshing.
+#[derive(Clone, Debug)]
+pub struct VoxelEdit {
+    pub center: Vec3,
+    pub radius: f32,
+    pub shape: BrushShape,
+    pub mode: BrushMode,
+
+    // -- Material given to samples turned solid by a fill, or by a smooth
+    // when there is no solid neighbour to take it from
+    pub material: MaterialId,
+}
+
+impl VoxelEdit {
+    pub fn dig(center: Vec3, radius: f32) -> Self {
+        Self { center, radius, shape: BrushShape::Sphere, mode: BrushMode::Dig, material: STONE }
+    }
+
+    pub fn fill(center: Vec3, radius: f32, material: MaterialId) -> Self {
+        Self { center, radius, shape: BrushShape::Sphere, mode: BrushMode::Fill, material }
+    }
+
+    pub fn smooth(center: Vec3, radius: f32) -> Self {
+        Self { center, radius, shape: BrushShape::Sphere, mode: BrushMode::Smooth, material: STONE }
+    }
+
+    pub fn with_shape(mut self, shape: BrushShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    // -- Signed distance to the surface of the brush, positive inside --
+    pub fn distance(&self, world: IVec3) -> f32 {
+        let offset = world.as_vec3() - self.center;
+
+        match self.shape {
+            BrushShape::Sphere => self.radius - offset.length(),
+            BrushShape::Cube => self.radius - offset.abs().max_element(),
+        }
+    }
+
+    // -- World samples the edit can change, min and max inclusive --
+    pub fn bounds(&self) -> (IVec3, IVec3) {
+        let extent = Vec3::splat(self.radius + 1.0);
+
+        (
+            (self.center - extent).floor().as_ivec3(),
+            (self.center + extent).ceil().as_ivec3(),
+        )
+    }
+
+    // -- New value of a sample, given a way to read the (unedited) neighbours --
+    pub fn apply(&self, world: IVec3, voxel: Voxel, neighbour: impl Fn(IVec3) -> Option<Voxel>) -> Voxel {
+        let distance = self.distance(world);
+        let mut voxel = voxel;
+
+        match self.mode {
+            BrushMode::Dig => {
+                voxel.density = voxel.density.min(-distance);
+            }
+
+            BrushMode::Fill => {
+                if distance > ISO_LEVEL && !voxel.is_solid() { voxel.material = self.material; }
+                voxel.density = voxel.density.max(distance);
+            }
+
+            BrushMode::Smooth => {
+                let weight = (distance / self.radius.max(f32::EPSILON)).clamp(0.0, 1.0);
+                if weight <= 0.0 { return voxel; }
+
+                let directions = [IVec3::X, -IVec3::X, IVec3::Y, -IVec3::Y, IVec3::Z, -IVec3::Z];
+                let neighbours: Vec<Voxel> = directions.iter()
+                    .filter_map(|direction| neighbour(world + *direction))
+                    .collect();
+
+                if neighbours.is_empty() { return voxel; }
+
+                let average = neighbours.iter().map(|next| next.density).sum::<f32>() / neighbours.len() as f32;
+                let was_solid = voxel.is_solid();
+                voxel.density += (average - voxel.density) * weight;
+
+                // -- Air that turned solid takes the material of the densest solid neighbour
+                if voxel.is_solid() && !was_solid {
+                    voxel.material = neighbours.iter()
+                        .filter(|next| next.is_solid())
+                        .max_by(|a, b| a.density.partial_cmp(&b.density).unwrap_or(std::cmp::Ordering::Equal))
+                        .map_or(self.material, |next| next.material);
+                }
+            }
+        }
+
+        voxel
+    }
+}
+
+// -- Work out every changed sample up front, so chunks that share a sample
+// all end up with the exact same value --
+pub fn edit_samples(
+    edit: &VoxelEdit,
+    read: impl Fn(IVec3) -> Option<Voxel>,
+) -> Vec<(IVec3, Voxel)> {
+    let (min, max) = edit.bounds();
+    let mut changed = Vec::new();
+
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let world = IVec3::new(x, y, z);
+
+                let voxel = match read(world) {
+                    Some(voxel) => voxel,
+                    None => continue,
+                };
+
+                let edited = edit.apply(world, voxel, &read);
+                if edited != voxel { changed.push((world, edited)); }
+            }
+        }
+    }
+
+    changed
+}
+
+// -- Write the changed samples into every chunk that stores them, draining
+// the fluid out of any that turned solid --
+pub fn write_samples(
+    commands: &mut Commands,
+    manager: &ChunkManager,
+    chunks: &mut Query<&mut Chunk>,
+    changed: &[(IVec3, Voxel)],
+) {
+    if changed.is_empty() { return; }
+
+    let mut min = changed[0].0;
+    let mut max = changed[0].0;
+
+    for (world, _) in changed.iter() {
+        min = min.min(*world);
+        max = max.max(*world);
+    }
+
+    // -- One extra chunk on each side for the padding
+    let from = chunk::chunk_coord(min) - IVec3::ONE;
+    let to = chunk::chunk_coord(max) + IVec3::ONE;
+
+    for z in from.z..=to.z {
+        for y in from.y..=to.y {
+            for x in from.x..=to.x {
+                let entity = match manager.get(IVec3::new(x, y, z)) {
+                    Some(entity) => entity,
+                    None => continue,
+                };
+
+                let mut chunk = match chunks.get_mut(entity) {
+                    Ok(chunk) => chunk,
+                    Err(_) => continue,
+                };
+
+                let mut touched = false;
+                let mut drained = false;
+
+                for (world, voxel) in changed.iter() {
+                    touched |= chunk.set_world(*world, *voxel);
+
+                    // -- Fluid cannot stay in a sample that was filled in
+                    let wet = chunk.fluid_world(*world).map_or(false, |fluid| !fluid.is_empty());
+                    if voxel.is_solid() && wet {
+                        drained |= chunk.set_fluid_world(*world, Fluid::default());
+                    }
+                }
+
+                if touched { commands.entity(entity).insert(ChunkDirty).insert(ChunkModified); }
+                if drained { commands.entity(entity).insert(FluidDirty); }
+            }
+        }
+    }
+}
+
+pub fn apply_edits(
+    mut commands: Commands,
+    mut edits: EventReader<VoxelEdit>,
+    mut connectivity: ResMut<Connectivity>,
+    manager: Res<ChunkManager>,
+    mut chunks: ParamSet<(Query<&Chunk>, Query<&mut Chunk>)>,
+) {
+    for edit in edits.iter() {
+        let changed = {
+            let read = chunks.p0();
+            edit_samples(edit, |world| manager.voxel(&read, world))
+        };
+
+        write_samples(&mut commands, &manager, &mut chunks.p1(), &changed);
+
+        if changed.is_empty() { continue; }
+
+        // -- Taking terrain away can leave pieces hanging in the air, and digging
+        // under an overhang or building one out can both overload it
+        let (min, max) = edit.bounds();
+        connectivity.check(min, max, edit.mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_engine::material::{AIR, DIRT, SAND};
+
+    #[test]
+    fn smooth_takes_the_densest_neighbours_material() {
+        let edit = VoxelEdit::smooth(Vec3::ZERO, 4.0);
+        let air = Voxel { density: -0.1, material: AIR };
+
+        let neighbour = |p: IVec3| Some(match p {
+            p if p == IVec3::NEG_Y => Voxel { density: 1.0, material: SAND },
+            p if p == IVec3::X => Voxel { density: 0.5, material: DIRT },
+            _ => Voxel { density: 0.2, material: DIRT },
+        });
+
+        let smoothed = edit.apply(IVec3::ZERO, air, neighbour);
+        assert!(smoothed.is_solid());
+        assert_eq!(smoothed.material, SAND);
+    }
+}
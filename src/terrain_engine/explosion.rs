@@ -0,0 +1,344 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::{ExternalImpulse, RigidBody, Velocity};
+
+use super::{
+    chunk::{Chunk, Voxel},
+    chunk_manager::ChunkManager,
+    connectivity::Connectivity,
+    edit::{write_samples, BrushMode},
+    material::{self, MaterialId, AIR},
+    noise::{self, NoiseLayer},
+    voxel_body::{self, VoxelBody},
+};
+
+/// Send this event to blow a hole in the terrain. Density is taken away in a
+/// rough sphere, less the harder the material is, some of what was removed is
+/// thrown out as debris and every dynamic body in reach gets pushed away.
+#[derive(Clone, Debug)]
+pub struct Explosion {
+    pub center: Vec3,
+    pub radius: f32,
+    pub power: f32,
+}
+
+pub struct ExplosionSettings {
+    // -- Bumps on the edge of the crater, as a fraction of the radius
+    pub noise: NoiseLayer,
+    pub roughness: f32,
+
+    // -- One in this many removed samples throws a piece of debris
+    pub debris_chance: u32,
+    pub max_debris: usize,
+    pub debris_radius: f32,
+    pub debris_speed: f32,
+
+    // -- Bodies are pushed up to this many radii away
+    pub impulse_reach: f32,
+    pub impulse_scale: f32,
+}
+
+impl Default for ExplosionSettings {
+    fn default() -> Self {
+        Self {
+            noise: NoiseLayer {
+                frequency: 0.35,
+                amplitude: 1.0,
+                octaves: 2,
+                lacunarity: 2.0,
+                persistence: 0.5,
+            },
+            roughness: 0.3,
+            debris_chance: 24,
+            max_debris: 12,
+            debris_radius: 1.2,
+            debris_speed: 0.15,
+            impulse_reach: 2.5,
+            impulse_scale: 0.2,
+        }
+    }
+}
+
+impl Explosion {
+    // -- World samples the explosion can change, min and max inclusive --
+    pub fn bounds(&self, settings: &ExplosionSettings) -> (IVec3, IVec3) {
+        let extent = Vec3::splat(self.radius * (1.0 + settings.roughness) + 1.0);
+
+        (
+            (self.center - extent).floor().as_ivec3(),
+            (self.center + extent).ceil().as_ivec3(),
+        )
+    }
+
+    // -- Every explosion at the same spot makes the same crater --
+    pub fn seed(&self) -> u32 {
+        noise::hash(self.center.round().as_ivec3(), 0)
+    }
+}
+
+/// New values of every sample the explosion changes. A sample loses
+/// `power / hardness` at the center, falling off to nothing at the noisy edge.
+pub fn carve_samples(
+    explosion: &Explosion,
+    settings: &ExplosionSettings,
+    read: impl Fn(IVec3) -> Option<Voxel>,
+) -> Vec<(IVec3, Voxel)> {
+    let (min, max) = explosion.bounds(settings);
+    let seed = explosion.seed();
+    let mut changed = Vec::new();
+
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let world = IVec3::new(x, y, z);
+
+                let voxel = match read(world) {
+                    Some(voxel) => voxel,
+                    None => continue,
+                };
+
+                let bump = settings.noise.sample(world.as_vec3(), seed) * settings.roughness;
+                let reach = explosion.radius * (1.0 + bump);
+
+                let falloff = 1.0 - world.as_vec3().distance(explosion.center) / reach.max(f32::EPSILON);
+                if falloff <= 0.0 { continue; }
+
+                // -- Air gets carved too, or the surface would not move back with the solid samples
+                let hardness = material::strength(voxel.material).max(1.0);
+                let carved = voxel.density - explosion.power * falloff / hardness;
+
+                // -- Never deeper than the crater itself, so filling it back in still works
+                let density = carved.max(voxel.density.min(-reach));
+
+                if density != voxel.density {
+                    changed.push((world, Voxel { density, ..voxel }));
+                }
+            }
+        }
+    }
+
+    changed
+}
+
+// -- A rough ball of the material, with sample (0, 0, 0) at the corner --
+pub fn debris_body(radius: f32, material: MaterialId, seed: u32) -> (IVec3, VoxelBody) {
+    // -- A sample of air all the way around, past anything the noise adds
+    let center = IVec3::splat(radius.ceil() as i32 + 1);
+    let dims = (center * 2 + IVec3::ONE).as_uvec3();
+
+    let mut voxels = Vec::with_capacity((dims.x * dims.y * dims.z) as usize);
+
+    for z in 0..dims.z as i32 {
+        for y in 0..dims.y as i32 {
+            for x in 0..dims.x as i32 {
+                let local = IVec3::new(x, y, z);
+                let density = radius - local.as_vec3().distance(center.as_vec3())
+                    + noise::gradient_noise(local.as_vec3() * 0.8, seed) * 0.3;
+
+                let material = if density > 0.0 { material } else { AIR };
+                voxels.push(Voxel { density, material });
+            }
+        }
+    }
+
+    (center, VoxelBody::from_voxels(dims, voxels))
+}
+
+// -- The first point from `start` along `direction` where a ball of `radius`
+// touches nothing solid, None if there is none within `reach` --
+pub fn clear_spot(
+    start: Vec3,
+    direction: Vec3,
+    radius: f32,
+    reach: f32,
+    read: impl Fn(IVec3) -> Option<Voxel>,
+) -> Option<Vec3> {
+    let extent = radius.ceil() as i32 + 1;
+
+    let blocked = |center: Vec3| {
+        let base = center.round().as_ivec3();
+
+        (-extent..=extent).any(|z| (-extent..=extent).any(|y| (-extent..=extent).any(|x| {
+            let world = base + IVec3::new(x, y, z);
+            world.as_vec3().distance(center) <= radius + 1.0 && read(world).map_or(false, |voxel| voxel.is_solid())
+        })))
+    };
+
+    // -- Half a sample at a time, fine enough not to skip past a gap
+    (0..=(reach * 2.0) as u32)
+        .map(|step| start + direction * step as f32 * 0.5)
+        .find(|center| !blocked(*center))
+}
+
+pub fn explode(
+    mut commands: Commands,
+    mut explosions: EventReader<Explosion>,
+    settings: Res<ExplosionSettings>,
+    mut connectivity: ResMut<Connectivity>,
+    manager: Res<ChunkManager>,
+    mut chunks: ParamSet<(Query<&Chunk>, Query<&mut Chunk>)>,
+) {
+    for explosion in explosions.iter() {
+        let seed = explosion.seed();
+
+        let (changed, removed) = {
+            let read = chunks.p0();
+            let sample = |world| manager.voxel(&read, world);
+
+            let changed = carve_samples(explosion, &settings, &sample);
+
+            // -- Solid samples that are gone, debris is made out of some of them
+            let removed: Vec<(IVec3, MaterialId)> = changed.iter()
+                .filter(|(_, voxel)| !voxel.is_solid())
+                .filter_map(|(world, _)| sample(*world).map(|voxel| (*world, voxel)))
+                .filter(|(_, voxel)| voxel.is_solid())
+                .map(|(world, voxel)| (world, voxel.material))
+                .collect();
+
+            (changed, removed)
+        };
+
+        write_samples(&mut commands, &manager, &mut chunks.p1(), &changed);
+
+        // -- The crater can leave pieces hanging or with nothing under them
+        if !changed.is_empty() {
+            let (min, max) = explosion.bounds(&settings);
+            connectivity.check(min, max, BrushMode::Dig);
+        }
+
+        let debris = removed.iter()
+            .filter(|(world, _)| noise::hash(*world, seed) % settings.debris_chance.max(1) == 0)
+            .take(settings.max_debris);
+
+        let read = chunks.p0();
+
+        for (world, material) in debris {
+            let (center, body) = debris_body(settings.debris_radius, *material, noise::hash(*world, seed ^ 1));
+
+            let offset = world.as_vec3() - explosion.center;
+            let falloff = (1.0 - offset.length() / explosion.radius.max(f32::EPSILON)).max(0.0);
+            let direction = (offset.try_normalize().unwrap_or(Vec3::Y) + Vec3::Y * 0.5).normalize();
+
+            // -- Moved out along its path until it is clear of the crater walls, or
+            // not thrown at all if it would start buried
+            let position = match clear_spot(
+                world.as_vec3(),
+                direction,
+                settings.debris_radius,
+                explosion.radius * 2.0,
+                |world| manager.voxel(&read, world),
+            ) {
+                Some(position) => position,
+                None => continue,
+            };
+
+            let entity = voxel_body::spawn(
+                &mut commands,
+                body,
+                Transform::from_translation(position - center.as_vec3()),
+                manager.material.clone(),
+            );
+
+            commands.entity(entity).insert(Velocity::linear(direction * explosion.power * falloff * settings.debris_speed));
+        }
+    }
+}
+
+// -- Push every dynamic body in reach straight away from the center, on top of
+// any impulse it already has this frame --
+pub fn push_bodies(
+    mut commands: Commands,
+    mut explosions: EventReader<Explosion>,
+    settings: Res<ExplosionSettings>,
+    mut bodies: Query<(Entity, &GlobalTransform, &RigidBody, Option<&mut ExternalImpulse>)>,
+) {
+    let mut impulses: HashMap<Entity, Vec3> = HashMap::default();
+
+    for explosion in explosions.iter() {
+        let reach = explosion.radius * settings.impulse_reach;
+
+        for (entity, transform, body, _) in bodies.iter() {
+            if *body != RigidBody::Dynamic { continue; }
+
+            let offset = transform.translation() - explosion.center;
+            let distance = offset.length();
+            if distance >= reach { continue; }
+
+            let direction = offset.try_normalize().unwrap_or(Vec3::Y);
+            let impulse = direction * explosion.power * (1.0 - distance / reach) * settings.impulse_scale;
+
+            *impulses.entry(entity).or_insert(Vec3::ZERO) += impulse;
+        }
+    }
+
+    for (entity, impulse) in impulses {
+        match bodies.get_mut(entity) {
+            Ok((_, _, _, Some(mut external))) => external.impulse += impulse,
+            Ok(_) => { commands.entity(entity).insert(ExternalImpulse { impulse, torque_impulse: Vec3::ZERO }); }
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_engine::material::{DIRT, STONE};
+
+    fn explosion() -> Explosion {
+        Explosion { center: Vec3::new(3.2, -1.0, 7.5), radius: 4.0, power: 60.0 }
+    }
+
+    // -- Solid ground of one material below y = 0 --
+    fn ground(material: MaterialId) -> impl Fn(IVec3) -> Option<Voxel> {
+        move |world| Some(Voxel { density: -world.y as f32 - 0.5, material })
+    }
+
+    #[test]
+    fn stone_loses_less_than_dirt() {
+        let settings = ExplosionSettings::default();
+        let center = explosion().center.round().as_ivec3();
+
+        let loss = |material| {
+            let read = ground(material);
+            let (_, voxel) = carve_samples(&explosion(), &settings, &read).into_iter()
+                .find(|(world, _)| *world == center)
+                .unwrap();
+
+            read(center).unwrap().density - voxel.density
+        };
+
+        assert!(loss(STONE) > 0.0);
+        assert!(loss(STONE) < loss(DIRT));
+    }
+
+    #[test]
+    fn same_explosion_same_crater() {
+        let settings = ExplosionSettings::default();
+
+        assert_eq!(
+            carve_samples(&explosion(), &settings, ground(DIRT)),
+            carve_samples(&explosion(), &settings, ground(DIRT)),
+        );
+
+        let (center, a) = debris_body(settings.debris_radius, STONE, 42);
+        let (_, b) = debris_body(settings.debris_radius, STONE, 42);
+
+        assert_eq!(a.dims(), b.dims());
+        assert!(a.get(center).unwrap().is_solid());
+
+        for z in 0..a.dims().z as i32 {
+            for y in 0..a.dims().y as i32 {
+                for x in 0..a.dims().x as i32 {
+                    let local = IVec3::new(x, y, z);
+                    assert_eq!(a.get(local), b.get(local));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn debris_starts_clear_of_the_ground() {
+        let spot = clear_spot(Vec3::new(0.0, -1.0, 0.0), Vec3::Y, 1.2, 8.0, ground(DIRT)).unwrap();
+        assert!(spot.y - 1.2 > 0.0);
+    }
+}
@@ -0,0 +1,558 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::RapierConfiguration;
+use std::collections::BTreeSet;
+
+use super::{
+    chunk::{self, Chunk, CHUNK_SIZE},
+    chunk_manager::{ChunkManager, ChunkModified, FluidDirty},
+    edit::VoxelEdit,
+    explosion::{Explosion, ExplosionSettings},
+};
+
+pub mod buoyancy;
+pub mod mesh;
+
+// -- Every sample stores one of these next to its fluid level --
+pub type FluidId = u8;
+
+pub const NONE: FluidId = 0;
+pub const WATER: FluidId = 1;
+pub const LAVA: FluidId = 2;
+
+// -- Every fluid that gets simulated and meshed --
+pub const FLUIDS: [FluidId; 2] = [WATER, LAVA];
+
+// -- Level of a sample that is filled to the top --
+pub const FULL: u8 = 255;
+
+const SIDEWAYS: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+const NEIGHBOURS: [IVec3; 6] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z];
+
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Fluid {
+    pub level: u8,
+    pub kind: FluidId,
+}
+
+impl Fluid {
+    pub fn full(kind: FluidId) -> Self {
+        Self { level: FULL, kind }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.level == 0
+    }
+
+    // -- How much of the sample is filled, 0 to 1 --
+    pub fn amount(&self) -> f32 {
+        self.level as f32 / FULL as f32
+    }
+
+    // -- Can `kind` flow in here without mixing two fluids --
+    fn accepts(&self, kind: FluidId) -> bool {
+        self.is_empty() || self.kind == kind
+    }
+}
+
+pub fn color(kind: FluidId) -> Color {
+    match kind {
+        WATER => Color::rgba(0.15, 0.35, 0.6, 0.6),
+        LAVA => Color::rgba(1.0, 0.35, 0.05, 0.95),
+        _ => Color::rgba(0.0, 0.0, 0.0, 0.0),
+    }
+}
+
+// -- Mass of a cubic unit, compared against `material::mass_density` for buoyancy --
+pub fn density(kind: FluidId) -> f32 {
+    match kind {
+        WATER => 1.0,
+        LAVA => 3.1,
+        _ => 0.0,
+    }
+}
+
+// -- Fraction of the velocity a fully submerged body loses per second, roughly --
+pub fn drag(kind: FluidId) -> f32 {
+    match kind {
+        WATER => 1.5,
+        LAVA => 6.0,
+        _ => 0.0,
+    }
+}
+
+// -- Thicker fluids only move every this many ticks --
+pub fn flow_interval(kind: FluidId) -> u64 {
+    match kind {
+        LAVA => 4,
+        _ => 1,
+    }
+}
+
+/// Send this event to pour fluid into the air inside a sphere, `NONE` drains it.
+#[derive(Clone, Debug)]
+pub struct FluidEdit {
+    pub center: Vec3,
+    pub radius: f32,
+    pub kind: FluidId,
+}
+
+/// Fluid moves as a cellular automaton at a fixed tick. Only the active
+/// samples are stepped, a sample stays active while it or a neighbour keeps
+/// changing, so fluid that has settled costs nothing.
+pub struct FluidSimulation {
+    pub tick: Timer,
+    pub ticks: u64,
+
+    pub updates_per_tick: usize,
+
+    // -- Thinner than this and it stops spreading sideways, or it would never settle
+    pub min_spread: u8,
+
+    // -- Ordered so the simulation always steps the samples in the same order
+    pub active: BTreeSet<(i32, i32, i32)>,
+
+    pub meshes_per_frame: usize,
+    pub materials: HashMap<FluidId, Handle<StandardMaterial>>,
+}
+
+impl Default for FluidSimulation {
+    fn default() -> Self {
+        Self {
+            tick: Timer::from_seconds(0.1, true),
+            ticks: 0,
+            updates_per_tick: 8192,
+            min_spread: 8,
+            active: BTreeSet::new(),
+            meshes_per_frame: 4,
+            materials: HashMap::default(),
+        }
+    }
+}
+
+impl FluidSimulation {
+    pub fn wake(&mut self, world: IVec3) {
+        self.active.insert(key(world));
+    }
+
+    // -- Wake the samples from min to max, inclusive --
+    pub fn wake_bounds(&mut self, min: IVec3, max: IVec3) {
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    self.wake(IVec3::new(x, y, z));
+                }
+            }
+        }
+    }
+}
+
+// -- Lowest samples first, so fluid falling down a column moves together --
+fn key(world: IVec3) -> (i32, i32, i32) {
+    (world.y, world.z, world.x)
+}
+
+fn from_key((y, z, x): (i32, i32, i32)) -> IVec3 {
+    IVec3::new(x, y, z)
+}
+
+/// One tick of the automaton over the active samples, in the order given.
+/// `read` returns None for samples fluid cannot enter, solid or unloaded.
+/// Fluid first falls as far as the sample below has room for, then what is
+/// left evens out with the lower neighbours around it. Returns the new value
+/// of every changed sample, and the samples that had to wait for their fluid
+/// to be allowed to move this tick.
+pub fn step(
+    active: &[IVec3],
+    tick: u64,
+    min_spread: u8,
+    read: impl Fn(IVec3) -> Option<Fluid>,
+) -> (HashMap<IVec3, Fluid>, Vec<IVec3>) {
+    let mut changed: HashMap<IVec3, Fluid> = HashMap::default();
+    let mut waiting = Vec::new();
+
+    // -- Later samples see what earlier ones did this tick
+    let get = |changed: &HashMap<IVec3, Fluid>, p: IVec3| changed.get(&p).copied().or_else(|| read(p));
+
+    for p in active.iter().copied() {
+        let mut fluid = match get(&changed, p) {
+            Some(fluid) if !fluid.is_empty() => fluid,
+            _ => continue,
+        };
+
+        if tick % flow_interval(fluid.kind) != 0 {
+            waiting.push(p);
+            continue;
+        }
+
+        let start = fluid;
+
+        // -- Fall
+        let below = p - IVec3::Y;
+
+        if let Some(under) = get(&changed, below).filter(|under| under.accepts(fluid.kind)) {
+            let moved = fluid.level.min(FULL - under.level);
+
+            if moved > 0 {
+                changed.insert(below, Fluid { level: under.level + moved, kind: fluid.kind });
+                fluid.level -= moved;
+            }
+        }
+
+        // -- Spread, towards the average of this sample and the lower neighbours
+        if fluid.level >= min_spread {
+            let lower: Vec<(IVec3, Fluid)> = SIDEWAYS.iter()
+                .map(|direction| p + *direction)
+                .filter_map(|next| get(&changed, next).map(|neighbour| (next, neighbour)))
+                .filter(|(_, neighbour)| neighbour.accepts(fluid.kind) && neighbour.level < fluid.level)
+                .collect();
+
+            let total = fluid.level as u32 + lower.iter().map(|(_, neighbour)| neighbour.level as u32).sum::<u32>();
+            let share = (total / (lower.len() as u32 + 1)) as u8;
+
+            for (next, neighbour) in lower {
+                if neighbour.level >= share { continue; }
+
+                let moved = (share - neighbour.level).min(fluid.level);
+                if moved == 0 { break; }
+
+                changed.insert(next, Fluid { level: neighbour.level + moved, kind: fluid.kind });
+                fluid.level -= moved;
+            }
+        }
+
+        if fluid != start {
+            if fluid.is_empty() { fluid = Fluid::default(); }
+            changed.insert(p, fluid);
+        }
+    }
+
+    (changed, waiting)
+}
+
+// -- Write the changed fluid into every chunk that stores it --
+pub fn write_fluids(
+    commands: &mut Commands,
+    manager: &ChunkManager,
+    chunks: &mut Query<&mut Chunk>,
+    changed: &[(IVec3, Fluid)],
+) {
+    if changed.is_empty() { return; }
+
+    let mut min = changed[0].0;
+    let mut max = changed[0].0;
+
+    for (world, _) in changed.iter() {
+        min = min.min(*world);
+        max = max.max(*world);
+    }
+
+    // -- One extra chunk on each side for the padding
+    let from = chunk::chunk_coord(min) - IVec3::ONE;
+    let to = chunk::chunk_coord(max) + IVec3::ONE;
+
+    for z in from.z..=to.z {
+        for y in from.y..=to.y {
+            for x in from.x..=to.x {
+                let entity = match manager.get(IVec3::new(x, y, z)) {
+                    Some(entity) => entity,
+                    None => continue,
+                };
+
+                let mut chunk = match chunks.get_mut(entity) {
+                    Ok(chunk) => chunk,
+                    Err(_) => continue,
+                };
+
+                let mut touched = false;
+
+                for (world, fluid) in changed.iter() {
+                    touched |= chunk.set_fluid_world(*world, *fluid);
+                }
+
+                if touched { commands.entity(entity).insert(FluidDirty).insert(ChunkModified); }
+            }
+        }
+    }
+}
+
+pub fn setup(
+    mut simulation: ResMut<FluidSimulation>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for kind in FLUIDS {
+        let material = materials.add(StandardMaterial {
+            base_color: color(kind),
+            emissive: if kind == LAVA { Color::rgb(0.9, 0.25, 0.0) } else { Color::BLACK },
+            perceptual_roughness: 0.1,
+            alpha_mode: AlphaMode::Blend,
+
+            // -- The surface has to be visible from below as well
+            double_sided: true,
+            cull_mode: None,
+            ..default()
+        });
+
+        simulation.materials.insert(kind, material);
+    }
+}
+
+pub fn apply_edits(
+    mut commands: Commands,
+    mut edits: EventReader<FluidEdit>,
+    mut simulation: ResMut<FluidSimulation>,
+    manager: Res<ChunkManager>,
+    mut chunks: ParamSet<(Query<&Chunk>, Query<&mut Chunk>)>,
+) {
+    for edit in edits.iter() {
+        let fluid = if edit.kind == NONE { Fluid::default() } else { Fluid::full(edit.kind) };
+
+        let extent = Vec3::splat(edit.radius);
+        let min = (edit.center - extent).floor().as_ivec3();
+        let max = (edit.center + extent).ceil().as_ivec3();
+
+        let changed = {
+            let read = chunks.p0();
+            let mut changed = Vec::new();
+
+            for z in min.z..=max.z {
+                for y in min.y..=max.y {
+                    for x in min.x..=max.x {
+                        let world = IVec3::new(x, y, z);
+                        if world.as_vec3().distance(edit.center) > edit.radius { continue; }
+
+                        // -- Only the air gets filled
+                        match manager.voxel(&read, world) {
+                            Some(voxel) if !voxel.is_solid() => {},
+                            _ => continue,
+                        }
+
+                        if manager.fluid(&read, world) != Some(fluid) { changed.push((world, fluid)); }
+                    }
+                }
+            }
+
+            changed
+        };
+
+        write_fluids(&mut commands, &manager, &mut chunks.p1(), &changed);
+        simulation.wake_bounds(min - IVec3::ONE, max + IVec3::ONE);
+    }
+}
+
+// -- Terrain that was dug away can let fluid flow again --
+pub fn wake_edits(
+    mut simulation: ResMut<FluidSimulation>,
+    mut edits: EventReader<VoxelEdit>,
+    mut explosions: EventReader<Explosion>,
+    settings: Res<ExplosionSettings>,
+) {
+    for edit in edits.iter() {
+        let (min, max) = edit.bounds();
+        simulation.wake_bounds(min - IVec3::ONE, max + IVec3::ONE);
+    }
+
+    for explosion in explosions.iter() {
+        let (min, max) = explosion.bounds(&settings);
+        simulation.wake_bounds(min - IVec3::ONE, max + IVec3::ONE);
+    }
+}
+
+// -- Fluid on the border of a new chunk could not flow into it before --
+pub fn wake_loaded(
+    mut simulation: ResMut<FluidSimulation>,
+    manager: Res<ChunkManager>,
+    chunks: Query<&Chunk>,
+    added: Query<&Chunk, Added<Chunk>>,
+) {
+    for chunk in added.iter() {
+        let mut nearby = NEIGHBOURS.iter()
+            .filter_map(|direction| manager.get(chunk.position + *direction))
+            .filter_map(|entity| chunks.get(entity).ok());
+
+        if !chunk.has_fluid() && !nearby.any(|neighbour| neighbour.has_fluid()) { continue; }
+
+        // -- The outer samples on both sides of each face
+        let border = [-1, 0, CHUNK_SIZE - 1, CHUNK_SIZE];
+
+        for z in -1..=CHUNK_SIZE {
+            for y in -1..=CHUNK_SIZE {
+                for x in -1..=CHUNK_SIZE {
+                    if ![x, y, z].iter().any(|value| border.contains(value)) { continue; }
+
+                    let world = chunk.local_to_world(IVec3::new(x, y, z));
+                    if manager.fluid(&chunks, world).map_or(false, |fluid| !fluid.is_empty()) {
+                        simulation.wake(world);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn simulate(
+    mut commands: Commands,
+    mut simulation: ResMut<FluidSimulation>,
+    rapier_config: Res<RapierConfiguration>,
+    time: Res<Time>,
+    manager: Res<ChunkManager>,
+    mut chunks: ParamSet<(Query<&Chunk>, Query<&mut Chunk>)>,
+) {
+    // -- Stands still with the rest of the physics
+    if !rapier_config.physics_pipeline_active { return; }
+    if !simulation.tick.tick(time.delta()).just_finished() { return; }
+
+    simulation.ticks += 1;
+
+    let keys: Vec<(i32, i32, i32)> = simulation.active.iter()
+        .take(simulation.updates_per_tick)
+        .copied()
+        .collect();
+
+    for sample in keys.iter() { simulation.active.remove(sample); }
+
+    let active: Vec<IVec3> = keys.into_iter().map(from_key).collect();
+
+    let (changed, waiting) = {
+        let read = chunks.p0();
+
+        step(&active, simulation.ticks, simulation.min_spread, |world| {
+            match manager.voxel(&read, world) {
+                Some(voxel) if !voxel.is_solid() => manager.fluid(&read, world),
+                _ => None,
+            }
+        })
+    };
+
+    // -- Everything that changed and whatever is next to it gets another look next tick
+    for world in waiting { simulation.wake(world); }
+
+    let mut changed: Vec<(IVec3, Fluid)> = changed.into_iter().collect();
+    changed.sort_by_key(|(world, _)| key(*world));
+
+    for (world, _) in changed.iter() {
+        simulation.wake(*world);
+
+        for direction in NEIGHBOURS {
+            simulation.wake(*world + direction);
+        }
+    }
+
+    write_fluids(&mut commands, &manager, &mut chunks.p1(), &changed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // -- A closed box of air, with the fluid kept outside of any chunk --
+    struct Tank {
+        min: IVec3,
+        max: IVec3,
+        fluid: HashMap<IVec3, Fluid>,
+        active: BTreeSet<(i32, i32, i32)>,
+        ticks: u64,
+    }
+
+    impl Tank {
+        fn new(min: IVec3, max: IVec3) -> Self {
+            Self { min, max, fluid: HashMap::default(), active: BTreeSet::new(), ticks: 0 }
+        }
+
+        fn pour(&mut self, world: IVec3, fluid: Fluid) {
+            self.fluid.insert(world, fluid);
+            self.active.insert(key(world));
+        }
+
+        fn read(&self, world: IVec3) -> Option<Fluid> {
+            let inside = world.cmpge(self.min).all() && world.cmple(self.max).all();
+            inside.then(|| self.fluid.get(&world).copied().unwrap_or_default())
+        }
+
+        fn total(&self) -> u32 {
+            self.fluid.values().map(|fluid| fluid.level as u32).sum()
+        }
+
+        // -- The same bookkeeping as the `simulate` system --
+        fn tick(&mut self) {
+            self.ticks += 1;
+
+            let active: Vec<IVec3> = std::mem::take(&mut self.active).into_iter().map(from_key).collect();
+            let (changed, waiting) = step(&active, self.ticks, 8, |world| self.read(world));
+
+            for world in waiting { self.active.insert(key(world)); }
+
+            for (world, fluid) in changed {
+                self.fluid.insert(world, fluid);
+                self.active.insert(key(world));
+
+                for direction in NEIGHBOURS {
+                    self.active.insert(key(world + direction));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fluid_is_conserved() {
+        let mut tank = Tank::new(IVec3::new(-3, 0, -3), IVec3::new(3, 6, 3));
+
+        for z in -1..=1 {
+            for x in -1..=1 {
+                tank.pour(IVec3::new(x, 5, z), Fluid::full(WATER));
+            }
+        }
+
+        let total = tank.total();
+
+        for _ in 0..200 {
+            tank.tick();
+            assert_eq!(tank.total(), total);
+        }
+    }
+
+    #[test]
+    fn fluid_falls_before_it_spreads() {
+        let tank = {
+            let mut tank = Tank::new(IVec3::new(-2, 0, -2), IVec3::new(2, 4, 2));
+            tank.pour(IVec3::new(0, 3, 0), Fluid::full(WATER));
+            tank
+        };
+
+        let (changed, waiting) = step(&[IVec3::new(0, 3, 0)], 1, 8, |world| tank.read(world));
+
+        assert!(waiting.is_empty());
+        assert_eq!(changed.len(), 2);
+        assert_eq!(changed[&IVec3::new(0, 2, 0)], Fluid::full(WATER));
+        assert!(changed[&IVec3::new(0, 3, 0)].is_empty());
+    }
+
+    #[test]
+    fn lava_waits_for_its_interval() {
+        let mut tank = Tank::new(IVec3::new(-2, 0, -2), IVec3::new(2, 4, 2));
+        tank.pour(IVec3::new(0, 3, 0), Fluid::full(LAVA));
+
+        for _ in 1..flow_interval(LAVA) {
+            tank.tick();
+            assert_eq!(tank.fluid[&IVec3::new(0, 3, 0)], Fluid::full(LAVA));
+            assert!(tank.active.contains(&key(IVec3::new(0, 3, 0))));
+        }
+
+        tank.tick();
+        assert!(tank.fluid[&IVec3::new(0, 3, 0)].is_empty());
+        assert_eq!(tank.fluid[&IVec3::new(0, 2, 0)], Fluid::full(LAVA));
+    }
+
+    #[test]
+    fn single_sample_settles() {
+        let mut tank = Tank::new(IVec3::new(-4, 0, -4), IVec3::new(4, 2, 4));
+        tank.pour(IVec3::new(0, 0, 0), Fluid::full(WATER));
+
+        for _ in 0..1000 {
+            if tank.active.is_empty() { break; }
+            tank.tick();
+        }
+
+        assert!(tank.active.is_empty());
+        assert_eq!(tank.total(), FULL as u32);
+    }
+}
@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{RapierConfiguration, Velocity};
+
+use super::{density, drag, Fluid};
+use crate::terrain_engine::{chunk::Chunk, chunk_manager::ChunkManager};
+
+// -- Points along the height of a body checked for fluid --
+const SAMPLE_POINTS: usize = 5;
+
+/// Lets a body float. Bodies lighter than the fluid they are in get pushed up,
+/// and anything in fluid is slowed down by its drag. This is an approximation:
+/// the body is sampled as a line along its own up axis, and the lift only
+/// moves it and never turns it, so there is no righting torque.
+#[derive(Component, Clone, Debug)]
+pub struct Buoyancy {
+    // -- Mass of a cubic unit of the body
+    pub density: f32,
+
+    // -- Local point the force acts on, and how far the body reaches along
+    // its local up axis on both sides of it
+    pub center: Vec3,
+    pub half_height: f32,
+}
+
+/// The fluid at a point, if the point is under its surface. Uses the same
+/// convention as the fluid mesh: the point belongs to the closest sample, and
+/// a sample holding `amount` has its surface at `amount - 0.5` above it.
+pub fn fluid_at(point: Vec3, read: impl Fn(IVec3) -> Option<Fluid>) -> Option<Fluid> {
+    let sample = (point + 0.5).floor();
+
+    let fluid = read(sample.as_ivec3()).filter(|fluid| !fluid.is_empty())?;
+    (point.y <= sample.y - 0.5 + fluid.amount()).then(|| fluid)
+}
+
+// -- How much of the body is under the surface, and the fluid it is in --
+fn submerged(
+    manager: &ChunkManager,
+    chunks: &Query<&Chunk>,
+    center: Vec3,
+    extent: Vec3,
+) -> Option<(f32, Fluid)> {
+    let mut found = None;
+    let mut covered = 0;
+
+    for i in 0..SAMPLE_POINTS {
+        let t = i as f32 / (SAMPLE_POINTS - 1) as f32;
+        let point = center + extent * (t * 2.0 - 1.0);
+
+        if let Some(fluid) = fluid_at(point, |p| manager.fluid(chunks, p)) {
+            covered += 1;
+            if found.is_none() { found = Some(fluid); }
+        }
+    }
+
+    found.map(|fluid| (covered as f32 / SAMPLE_POINTS as f32, fluid))
+}
+
+pub fn float(
+    rapier_config: Res<RapierConfiguration>,
+    time: Res<Time>,
+    manager: Res<ChunkManager>,
+    chunks: Query<&Chunk>,
+    mut bodies: Query<(&GlobalTransform, &Buoyancy, &mut Velocity)>,
+) {
+    if !rapier_config.physics_pipeline_active { return; }
+
+    let delta = time.delta_seconds();
+
+    for (transform, buoyancy, mut velocity) in bodies.iter_mut() {
+        let matrix = transform.compute_matrix();
+        let center = matrix.transform_point3(buoyancy.center);
+        let extent = matrix.transform_vector3(Vec3::Y).normalize_or_zero() * buoyancy.half_height;
+
+        let (fraction, fluid) = match submerged(&manager, &chunks, center, extent) {
+            Some(submerged) => submerged,
+            None => continue,
+        };
+
+        // -- Archimedes, the displaced fluid over the mass of the body
+        let lift = density(fluid.kind) / buoyancy.density.max(f32::EPSILON) * fraction;
+        velocity.linvel -= rapier_config.gravity * lift * delta;
+
+        let slow = 1.0 / (1.0 + drag(fluid.kind) * fraction * delta);
+        velocity.linvel *= slow;
+        velocity.angvel *= slow;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_engine::fluid::{FULL, WATER};
+
+    fn half_full(p: IVec3) -> Option<Fluid> {
+        (p == IVec3::new(0, 4, 0)).then(|| Fluid { level: FULL / 2 + 1, kind: WATER })
+    }
+
+    #[test]
+    fn surface_matches_the_mesh() {
+        // -- The sample at y = 4 covers 3.5 to 4.5, so it is filled up to about 4.0
+        let surface = 4.0 - 0.5 + half_full(IVec3::new(0, 4, 0)).unwrap().amount();
+
+        assert!(fluid_at(Vec3::new(0.0, 3.6, 0.0), half_full).is_some());
+        assert!(fluid_at(Vec3::new(0.2, surface - 0.01, -0.3), half_full).is_some());
+        assert!(fluid_at(Vec3::new(0.0, surface + 0.01, 0.0), half_full).is_none());
+
+        // -- Belongs to the empty sample above, even though it floors to 4
+        assert!(fluid_at(Vec3::new(0.0, 4.6, 0.0), half_full).is_none());
+    }
+
+    #[test]
+    fn full_sample_reaches_its_top() {
+        let full = |p: IVec3| (p == IVec3::ZERO).then(|| Fluid { level: FULL, kind: WATER });
+
+        assert!(fluid_at(Vec3::new(0.0, -0.49, 0.0), full).is_some());
+        assert!(fluid_at(Vec3::new(0.0, 0.49, 0.0), full).is_some());
+        assert!(fluid_at(Vec3::new(0.0, 0.51, 0.0), full).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+use bevy::{pbr::NotShadowCaster, prelude::*};
+
+use super::{FluidId, FluidSimulation, FLUIDS};
+use crate::components::Player;
+use crate::terrain_engine::{
+    chunk::{self, mesher::{self, MeshData}, Chunk},
+    chunk_manager::{player_chunk, FluidDirty},
+};
+
+// -- A child of a chunk holding the surface of one fluid in it --
+#[derive(Component, Clone, Copy, Debug)]
+pub struct FluidMesh(pub FluidId);
+
+/// The field the fluid surface is marched from, at the chunk's samples. A
+/// sample is `amount - 0.5`, so a full sample has its surface half a sample
+/// above it. Solid samples take the highest value next to them, which pushes
+/// the surface into the terrain instead of leaving a gap along the walls.
+pub fn fluid_field(chunk: &Chunk, kind: FluidId) -> Vec<f32> {
+    let dims = Chunk::dims().as_ivec3();
+    let levels = chunk.fluid_levels();
+    let kinds = chunk.fluid_kinds();
+    let densities = chunk.densities();
+
+    let index = |p: IVec3| (p.x + p.y * dims.x + p.z * dims.x * dims.y) as usize;
+
+    let open: Vec<f32> = levels.iter()
+        .zip(kinds.iter())
+        .map(|(level, fluid)| if *fluid == kind { *level as f32 / super::FULL as f32 - 0.5 } else { -0.5 })
+        .collect();
+
+    let mut field = open.clone();
+
+    for z in 0..dims.z {
+        for y in 0..dims.y {
+            for x in 0..dims.x {
+                let p = IVec3::new(x, y, z);
+                if densities[index(p)] <= chunk::ISO_LEVEL { continue; }
+
+                field[index(p)] = super::NEIGHBOURS.iter()
+                    .map(|direction| p + *direction)
+                    .filter(|next| next.cmpge(IVec3::ZERO).all() && next.cmplt(dims).all())
+                    .filter(|next| densities[index(*next)] <= chunk::ISO_LEVEL)
+                    .map(|next| open[index(next)])
+                    .fold(-0.5, f32::max);
+            }
+        }
+    }
+
+    field
+}
+
+pub fn mesh(chunk: &Chunk, kind: FluidId) -> MeshData {
+    let (min, max) = Chunk::mesh_region();
+    mesher::march_region(&fluid_field(chunk, kind), Chunk::dims(), min, max, 0.0)
+}
+
+// -- Rebuild the fluid surfaces of chunks whose fluid changed, closest first --
+pub fn mesh_fluids(
+    mut commands: Commands,
+    simulation: Res<FluidSimulation>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    chunks: Query<(Entity, &Chunk, Option<&Children>), With<FluidDirty>>,
+    surfaces: Query<&FluidMesh>,
+    player: Query<&Transform, With<Player>>,
+) {
+    let center = player_chunk(&player).unwrap_or_default();
+
+    let mut dirty: Vec<(Entity, &Chunk, Option<&Children>)> = chunks.iter().collect();
+    dirty.sort_by_key(|(_, chunk, _)| (chunk.position - center).abs().max_element());
+
+    for (entity, chunk, children) in dirty.into_iter().take(simulation.meshes_per_frame) {
+        commands.entity(entity).remove::<FluidDirty>();
+
+        for kind in FLUIDS {
+            let data = mesh(chunk, kind);
+
+            let existing = children.and_then(|children| {
+                children.iter().copied().find(|child| surfaces.get(*child).map_or(false, |surface| surface.0 == kind))
+            });
+
+            match (existing, data.is_empty()) {
+                (Some(child), true) => commands.entity(child).despawn_recursive(),
+                (Some(child), false) => { commands.entity(child).insert(meshes.add(data.into_mesh())); },
+                (None, true) => {},
+                (None, false) => {
+                    let child = commands.spawn_bundle(PbrBundle {
+                        mesh: meshes.add(data.into_mesh()),
+                        material: simulation.materials.get(&kind).cloned().unwrap_or_default(),
+                        ..default()
+                    })
+                    .insert(FluidMesh(kind))
+                    .insert(NotShadowCaster)
+                    .id();
+
+                    commands.entity(entity).add_child(child);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use bevy::prelude::*;
+
+use super::{
+    chunk::{Chunk, Voxel, CHUNK_PADDING, CHUNK_SAMPLES, ISO_LEVEL},
+    material,
+    noise::NoiseLayer,
+};
+
+// -- Bump this whenever the output for a given seed changes --
+pub const GENERATOR_VERSION: u32 = 1;
+
+/// Settings for the terrain density function. The same seed and settings
+/// always produce the same world, whatever order the chunks are generated in.
+/// `terrain_generator.wgsl` is a GPU twin of it, kept in step by the tests,
+/// but chunks are only generated on the CPU for now.
+#[derive(Clone, Debug)]
+pub struct TerrainGenerator {
+    pub seed: u32,
+    pub base_height: f32,
+
+    // -- Large, slow rolling height changes
+    pub continents: NoiseLayer,
+
+    // -- Smaller bumps on top of the continents
+    pub hills: NoiseLayer,
+
+    // -- 3D noise, anything above the threshold is carved out
+    pub caves: NoiseLayer,
+    pub cave_threshold: f32,
+
+    // -- How deep the grass and dirt layers go before it turns to stone
+    pub grass_depth: f32,
+    pub dirt_depth: f32,
+}
+
+impl Default for TerrainGenerator {
+    fn default() -> Self {
+        Self {
+            seed: 1337,
+            base_height: 0.0,
+            continents: NoiseLayer {
+                frequency: 0.004,
+                amplitude: 24.0,
+                octaves: 3,
+                lacunarity: 2.0,
+                persistence: 0.5,
+            },
+            hills: NoiseLayer {
+                frequency: 0.03,
+                amplitude: 6.0,
+                octaves: 4,
+                lacunarity: 2.0,
+                persistence: 0.5,
+            },
+            caves: NoiseLayer {
+                frequency: 0.05,
+                amplitude: 1.0,
+                octaves: 2,
+                lacunarity: 2.0,
+                persistence: 0.5,
+            },
+            cave_threshold: 0.3,
+            grass_depth: 1.0,
+            dirt_depth: 4.0,
+        }
+    }
+}
+
+impl TerrainGenerator {
+    // -- Surface height of the column at the given world x/z --
+    pub fn height(&self, x: i32, z: i32) -> f32 {
+        let position = Vec3::new(x as f32, 0.0, z as f32);
+
+        self.base_height
+            + self.continents.sample(position, self.seed)
+            + self.hills.sample(position, self.seed.wrapping_add(100))
+    }
+
+    pub fn sample(&self, world: IVec3) -> Voxel {
+        self.sample_with_height(world, self.height(world.x, world.z))
+    }
+
+    fn sample_with_height(&self, world: IVec3, height: f32) -> Voxel {
+        let depth = height - world.y as f32;
+        let mut density = depth;
+
+        // -- Carve out caves, the noise only matters close to the threshold
+        let cave = self.caves.sample(world.as_vec3(), self.seed.wrapping_add(200));
+        density = density.min((self.cave_threshold - cave) * 8.0);
+
+        let material = if density <= ISO_LEVEL {
+            material::AIR
+        } else if depth < self.grass_depth {
+            material::GRASS
+        } else if depth < self.dirt_depth {
+            material::DIRT
+        } else {
+            material::STONE
+        };
+
+        Voxel { density, material }
+    }
+
+    // -- Fill a whole chunk, only computing the height once per column --
+    pub fn fill_chunk(&self, chunk: &mut Chunk) {
+        let origin = chunk.origin() - IVec3::splat(CHUNK_PADDING);
+        let mut heights = Vec::with_capacity((CHUNK_SAMPLES * CHUNK_SAMPLES) as usize);
+
+        for z in 0..CHUNK_SAMPLES {
+            for x in 0..CHUNK_SAMPLES {
+                heights.push(self.height(origin.x + x, origin.z + z));
+            }
+        }
+
+        chunk.fill(|world| {
+            let column = world - origin;
+            let height = heights[(column.x + column.z * CHUNK_SAMPLES) as usize];
+
+            self.sample_with_height(world, height)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_engine::chunk::CHUNK_SIZE;
+    use std::borrow::Cow;
+    use wgpu::util::DeviceExt;
+
+    const SHADER: &str = include_str!("../../assets/shaders/terrain_generator.wgsl");
+
+    fn generate(generator: &TerrainGenerator, position: IVec3) -> Chunk {
+        let mut chunk = Chunk::new(position);
+        generator.fill_chunk(&mut chunk);
+        chunk
+    }
+
+    #[test]
+    fn sample_is_deterministic() {
+        let generator = TerrainGenerator { seed: 42, ..Default::default() };
+        let points = [IVec3::new(0, 0, 0), IVec3::new(-17, 3, 250), IVec3::new(1000, -40, -999)];
+
+        for point in points {
+            let first = generator.sample(point);
+            let again = TerrainGenerator { seed: 42, ..Default::default() }.sample(point);
+
+            assert_eq!(first.density.to_bits(), again.density.to_bits());
+            assert_eq!(first.material, again.material);
+        }
+    }
+
+    #[test]
+    fn matches_golden_values() {
+        // -- Worked out once for the default settings, any machine has to agree on
+        // these bits or it generates a different world for the same seed
+        let generator = TerrainGenerator::default();
+        let golden = [
+            (IVec3::new(-17, 3, 250), 0x3f5e_63b2, 0xc008_6714),
+            (IVec3::new(1000, -40, -999), 0xbe21_5056, 0x402a_4568),
+            (IVec3::new(5, -12, 7), 0xbb45_5d80, 0x4089_827d),
+        ];
+
+        for (point, height, density) in golden {
+            assert_eq!(generator.height(point.x, point.z).to_bits(), height, "height at {:?}", point);
+            assert_eq!(generator.sample(point).density.to_bits(), density, "density at {:?}", point);
+        }
+    }
+
+    #[test]
+    fn chunks_agree_with_sample() {
+        let generator = TerrainGenerator { seed: 99, ..Default::default() };
+        let chunk = generate(&generator, IVec3::new(2, -1, -5));
+
+        // -- Owned samples and padding both match sampling the point on its own
+        for local in [IVec3::ZERO, IVec3::splat(-1), IVec3::new(CHUNK_SIZE, 5, CHUNK_SIZE + 1), IVec3::new(7, 31, 19)] {
+            let voxel = chunk.get(local).unwrap();
+            let expected = generator.sample(chunk.local_to_world(local));
+
+            assert_eq!(voxel.density.to_bits(), expected.density.to_bits());
+            assert_eq!(voxel.material, expected.material);
+        }
+    }
+
+    fn layer(layer: &NoiseLayer) -> [f32; 4] {
+        [layer.frequency, layer.amplitude, layer.lacunarity, layer.persistence]
+    }
+
+    // -- Params as laid out in the uniform of terrain_generator.wgsl --
+    fn params_bytes(generator: &TerrainGenerator, origin: IVec3, dims: UVec3) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(112);
+        let float = |bytes: &mut Vec<u8>, value: f32| bytes.extend_from_slice(&value.to_le_bytes());
+
+        for value in origin.to_array() { bytes.extend_from_slice(&value.to_le_bytes()); }
+        bytes.extend_from_slice(&generator.seed.to_le_bytes());
+        for value in dims.to_array() { bytes.extend_from_slice(&value.to_le_bytes()); }
+        float(&mut bytes, generator.base_height);
+
+        for noise in [&generator.continents, &generator.hills, &generator.caves] {
+            for value in layer(noise) { float(&mut bytes, value); }
+        }
+
+        for noise in [&generator.continents, &generator.hills, &generator.caves] {
+            bytes.extend_from_slice(&noise.octaves.to_le_bytes());
+        }
+
+        float(&mut bytes, generator.cave_threshold);
+        float(&mut bytes, generator.grass_depth);
+        float(&mut bytes, generator.dirt_depth);
+        bytes.resize(112, 0);
+
+        bytes
+    }
+
+    // -- Densities and materials of a grid starting at `origin`, x first then y then z --
+    fn generate_on_gpu(generator: &TerrainGenerator, origin: IVec3, dims: UVec3) -> (Vec<f32>, Vec<u32>) {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: true,
+            compatible_surface: None,
+        })).expect("no software adapter available");
+
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: adapter.limits(),
+        }, None)).expect("the software adapter did not give a device");
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER)),
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: None,
+            layout: None,
+            module: &module,
+            entry_point: "generate",
+        });
+
+        let size = (dims.x * dims.y * dims.z) as u64 * 4;
+        let empty = |usage| device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage,
+            mapped_at_creation: false,
+        });
+
+        let storage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC;
+        let staging = wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST;
+
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &params_bytes(generator, origin, dims),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let densities = empty(storage);
+        let materials = empty(storage);
+        let staging_densities = empty(staging);
+        let staging_materials = empty(staging);
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: params.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: densities.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: materials.as_entire_binding() },
+            ],
+        });
+
+        // -- Matches @workgroup_size in terrain_generator.wgsl
+        let workgroups = (dims + UVec3::splat(3)) / 4;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(workgroups.x, workgroups.y, workgroups.z);
+        }
+
+        encoder.copy_buffer_to_buffer(&densities, 0, &staging_densities, 0, size);
+        encoder.copy_buffer_to_buffer(&materials, 0, &staging_materials, 0, size);
+        queue.submit(Some(encoder.finish()));
+
+        staging_densities.slice(..).map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        staging_materials.slice(..).map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+
+        let densities = bevy::core::cast_slice::<u8, f32>(&staging_densities.slice(..).get_mapped_range()).to_vec();
+        let materials = bevy::core::cast_slice::<u8, u32>(&staging_materials.slice(..).get_mapped_range()).to_vec();
+
+        (densities, materials)
+    }
+
+    #[test]
+    #[ignore = "needs a wgpu software adapter, run with `cargo test -- --ignored`"]
+    fn gpu_matches_cpu() {
+        let generator = TerrainGenerator { seed: 21, ..Default::default() };
+
+        // -- Around the surface, so there is air, grass, dirt, stone and some caves
+        let origin = IVec3::new(-9, -14, 30);
+        let dims = UVec3::new(10, 20, 9);
+
+        let (densities, materials) = generate_on_gpu(&generator, origin, dims);
+        let mut index = 0;
+
+        for z in 0..dims.z as i32 {
+            for y in 0..dims.y as i32 {
+                for x in 0..dims.x as i32 {
+                    let world = origin + IVec3::new(x, y, z);
+                    let expected = generator.sample(world);
+                    let depth = generator.height(world.x, world.z) - world.y as f32;
+
+                    assert!(
+                        (densities[index] - expected.density).abs() <= 1e-3,
+                        "density at {:?}: {} on the GPU, {} on the CPU", world, densities[index], expected.density,
+                    );
+
+                    // -- Rounding can tip samples right on a boundary either way
+                    let boundary = [expected.density, depth - generator.grass_depth, depth - generator.dirt_depth]
+                        .iter()
+                        .any(|distance| distance.abs() <= 1e-3);
+
+                    if !boundary {
+                        assert_eq!(materials[index], expected.material as u32, "material at {:?}", world);
+                    }
+
+                    index += 1;
+                }
+            }
+        }
+    }
+}
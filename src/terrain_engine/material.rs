@@ -0,0 +1,44 @@
+use bevy::prelude::Color;
+
+// -- Every voxel stores one of these ids next to its density --
+pub type MaterialId = u8;
+
+pub const AIR: MaterialId = 0;
+pub const STONE: MaterialId = 1;
+pub const DIRT: MaterialId = 2;
+pub const GRASS: MaterialId = 3;
+pub const SAND: MaterialId = 4;
+
+pub fn color(material: MaterialId) -> Color {
+    match material {
+        STONE => Color::rgb(0.45, 0.45, 0.48),
+        DIRT => Color::rgb(0.4, 0.3, 0.2),
+        GRASS => Color::rgb(0.3, 0.5, 0.3),
+        SAND => Color::rgb(0.8, 0.75, 0.55),
+        _ => Color::rgba(0.0, 0.0, 0.0, 0.0),
+    }
+}
+
+// -- Mass of a cubic unit of the material, used for the mass of voxel bodies
+// and as the weight a sample puts on whatever holds it up --
+pub fn mass_density(material: MaterialId) -> f32 {
+    match material {
+        STONE => 2.6,
+        DIRT => 1.5,
+        GRASS => 1.4,
+        SAND => 1.6,
+        _ => 0.0,
+    }
+}
+
+// -- How much weight a sample can hold up sideways or hanging below it,
+// strength / weight is roughly how far an overhang of it can reach --
+pub fn strength(material: MaterialId) -> f32 {
+    match material {
+        STONE => 30.0,
+        DIRT => 8.0,
+        GRASS => 6.0,
+        SAND => 2.0,
+        _ => 0.0,
+    }
+}
@@ -1,11 +1,67 @@
-use bevy::prelude::{Plugin, App, StartupStage};
+use bevy::prelude::{error, Plugin, App, StartupStage, CoreStage, ParallelSystemDescriptorCoercion};
 
 pub mod chunk;  
+pub mod chunk_manager;
+pub mod connectivity;
+pub mod edit;
+pub mod explosion;
+pub mod fluid;
+pub mod generator;
+pub mod material;
+pub mod noise;
+pub mod raycast;
+pub mod region;
+pub mod stress;
+pub mod voxel_body;
+pub mod world_save;
 
 pub struct VoxelEnginePlugin;   
 
 impl Plugin for VoxelEnginePlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system_to_stage(StartupStage::PostStartup, chunk::new);
+        app.init_resource::<chunk_manager::ChunkManager>();
+
+        // -- The world picks the seed, a new world gets a random one. A world
+        // that can not be read is not played, it would only get overwritten
+        let save = world_save::WorldSave::open(&world_save::world_name()).unwrap_or_else(|err| {
+            error!("{}", err);
+            std::process::exit(1);
+        });
+        app.insert_resource(generator::TerrainGenerator { seed: save.meta.seed, ..Default::default() });
+        app.insert_resource(save);
+
+        app.init_resource::<raycast::VoxelTarget>();
+        app.init_resource::<connectivity::Connectivity>();
+        app.init_resource::<stress::StressAnalysis>();
+        app.init_resource::<explosion::ExplosionSettings>();
+        app.init_resource::<fluid::FluidSimulation>();
+        app.add_event::<edit::VoxelEdit>();
+        app.add_event::<explosion::Explosion>();
+        app.add_event::<fluid::FluidEdit>();
+        app.add_startup_system_to_stage(StartupStage::PostStartup, chunk_manager::setup);
+        app.add_startup_system_to_stage(StartupStage::PostStartup, fluid::setup);
+
+        app.add_system(world_save::restore_player.before(chunk_manager::load_chunks));
+        app.add_system_to_stage(CoreStage::Last, world_save::autosave);
+
+        app.add_system(edit::apply_edits);
+        app.add_system(explosion::explode.after(edit::apply_edits).before(connectivity::detach_islands));
+        app.add_system(explosion::push_bodies);
+        app.add_system(connectivity::detach_islands.after(edit::apply_edits));
+        app.add_system(stress::collapse.after(connectivity::detach_islands));
+        app.add_system(fluid::apply_edits);
+        app.add_system(fluid::wake_edits);
+        app.add_system(fluid::wake_loaded);
+        app.add_system(fluid::simulate.after(fluid::apply_edits).after(fluid::wake_edits).after(fluid::wake_loaded));
+        app.add_system(fluid::mesh::mesh_fluids);
+        app.add_system(fluid::buoyancy::float);
+        app.add_system(voxel_body::apply_edits);
+        app.add_system(voxel_body::mesh_bodies);
+        app.add_system(chunk_manager::load_chunks);
+        app.add_system(chunk_manager::unload_chunks);
+        app.add_system(voxel_body::unload_bodies.after(chunk_manager::unload_chunks));
+        app.add_system(chunk_manager::mesh_chunks);
+        app.add_system(chunk_manager::receive_meshes);
+        app.add_system(raycast::update_target);
     }
 }
\ No newline at end of file
@@ -0,0 +1,83 @@
+use bevy::math::{IVec3, Vec3};
+
+// -- Only integer hashing and plain float math, so the same inputs always give
+// bit identical results no matter what order the chunks are generated in --
+
+#[derive(Clone, Debug)]
+pub struct NoiseLayer {
+    pub frequency: f32,
+    pub amplitude: f32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub persistence: f32,
+}
+
+impl NoiseLayer {
+    // -- Fractal sum of gradient noise, roughly in -amplitude..amplitude --
+    pub fn sample(&self, position: Vec3, seed: u32) -> f32 {
+        let mut frequency = self.frequency;
+        let mut amplitude = 1.0;
+        let mut total = 0.0;
+        let mut range = 0.0;
+
+        for octave in 0..self.octaves {
+            total += gradient_noise(position * frequency, seed.wrapping_add(octave)) * amplitude;
+            range += amplitude;
+
+            frequency *= self.lacunarity;
+            amplitude *= self.persistence;
+        }
+
+        if range == 0.0 { return 0.0; }
+        total / range * self.amplitude
+    }
+}
+
+// -- Integer hash of a lattice point, see https://nullprogram.com/blog/2018/07/31/ --
+pub fn hash(p: IVec3, seed: u32) -> u32 {
+    let mut h = seed
+        ^ (p.x as u32).wrapping_mul(0x8da6_b343)
+        ^ (p.y as u32).wrapping_mul(0xd816_3841)
+        ^ (p.z as u32).wrapping_mul(0xcb1a_b31f);
+
+    h = (h ^ (h >> 16)).wrapping_mul(0x7feb_352d);
+    h = (h ^ (h >> 15)).wrapping_mul(0x846c_a68b);
+    h ^ (h >> 16)
+}
+
+// -- The 12 edge directions of a cube, as in improved Perlin noise --
+const GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0], [0.0, -1.0, 1.0], [0.0, 1.0, -1.0], [0.0, -1.0, -1.0],
+];
+
+fn corner(cell: IVec3, offset: IVec3, local: Vec3, seed: u32) -> f32 {
+    let gradient = Vec3::from(GRADIENTS[(hash(cell + offset, seed) % 12) as usize]);
+    gradient.dot(local - offset.as_vec3())
+}
+
+fn fade(t: Vec3) -> Vec3 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+// -- 3D gradient noise in roughly -1..1 --
+pub fn gradient_noise(position: Vec3, seed: u32) -> f32 {
+    let floor = position.floor();
+    let cell = floor.as_ivec3();
+    let local = position - floor;
+    let t = fade(local);
+
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let c = |x, y, z| corner(cell, IVec3::new(x, y, z), local, seed);
+
+    let x00 = lerp(c(0, 0, 0), c(1, 0, 0), t.x);
+    let x10 = lerp(c(0, 1, 0), c(1, 1, 0), t.x);
+    let x01 = lerp(c(0, 0, 1), c(1, 0, 1), t.x);
+    let x11 = lerp(c(0, 1, 1), c(1, 1, 1), t.x);
+
+    let y0 = lerp(x00, x10, t.y);
+    let y1 = lerp(x01, x11, t.y);
+
+    lerp(y0, y1, t.z)
+}
@@ -0,0 +1,264 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{QueryFilter, RapierContext};
+
+use crate::components::OrbitCamera;
+use super::{
+    chunk::{Chunk, Voxel, ISO_LEVEL},
+    chunk_manager::ChunkManager,
+    material::{MaterialId, AIR},
+    voxel_body::VoxelBody,
+};
+
+// -- Bisection steps used to refine the hit against the iso surface --
+const REFINE_STEPS: u32 = 12;
+
+// -- Extra samples taken inside each cell, so a ray grazing a thin
+// surface is not missed --
+const CELL_SAMPLES: u32 = 4;
+
+#[derive(Clone, Copy, Debug)]
+pub struct VoxelHit {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub distance: f32,
+
+    // -- Solid sample closest to the hit and its material. The sample is in
+    // the grid of `body` when a body was hit, in the terrain otherwise
+    pub voxel: IVec3,
+    pub material: MaterialId,
+    pub body: Option<Entity>,
+}
+
+// -- What the camera is currently looking at, updated every frame --
+pub struct VoxelTarget {
+    pub max_distance: f32,
+    pub hit: Option<VoxelHit>,
+}
+
+impl Default for VoxelTarget {
+    fn default() -> Self {
+        Self { max_distance: 64.0, hit: None }
+    }
+}
+
+// -- Trilinear interpolation of the density field, missing samples are air --
+pub fn density_at(position: Vec3, sample: &impl Fn(IVec3) -> Option<Voxel>) -> f32 {
+    let floor = position.floor();
+    let cell = floor.as_ivec3();
+    let t = position - floor;
+
+    let d = |x, y, z| {
+        sample(cell + IVec3::new(x, y, z))
+            .map(|voxel| voxel.density)
+            .unwrap_or_else(|| Voxel::air().density)
+    };
+
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+    let x00 = lerp(d(0, 0, 0), d(1, 0, 0), t.x);
+    let x10 = lerp(d(0, 1, 0), d(1, 1, 0), t.x);
+    let x01 = lerp(d(0, 0, 1), d(1, 0, 1), t.x);
+    let x11 = lerp(d(0, 1, 1), d(1, 1, 1), t.x);
+
+    lerp(lerp(x00, x10, t.y), lerp(x01, x11, t.y), t.z)
+}
+
+// -- Surface normal from the gradient of the interpolated field --
+pub fn normal_at(position: Vec3, sample: &impl Fn(IVec3) -> Option<Voxel>) -> Vec3 {
+    let e = 0.1;
+    let d = |offset: Vec3| density_at(position + offset, sample);
+
+    let gradient = Vec3::new(
+        d(Vec3::X * e) - d(-Vec3::X * e),
+        d(Vec3::Y * e) - d(-Vec3::Y * e),
+        d(Vec3::Z * e) - d(-Vec3::Z * e),
+    );
+
+    (-gradient).normalize_or_zero()
+}
+
+/// Walks the cells along the ray (DDA) and returns the first point where the
+/// interpolated density crosses the iso level, refined with a bisection.
+pub fn raycast(
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    sample: impl Fn(IVec3) -> Option<Voxel>,
+) -> Option<VoxelHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO { return None; }
+
+    let solid = |t: f32| density_at(origin + direction * t, &sample) > ISO_LEVEL;
+
+    // -- Started inside the terrain
+    if solid(0.0) { return Some(hit(origin, direction, 0.0, &sample)); }
+
+    // -- DDA setup, only the distances to the next cell boundaries are
+    // needed since the density is sampled along the ray directly
+    let cell = origin.floor().as_ivec3();
+
+    let next_boundary = |axis: f32, position: f32, cell: i32| -> f32 {
+        if axis > 0.0 { ((cell + 1) as f32 - position) / axis }
+        else if axis < 0.0 { (cell as f32 - position) / axis }
+        else { f32::INFINITY }
+    };
+
+    let mut t_max = Vec3::new(
+        next_boundary(direction.x, origin.x, cell.x),
+        next_boundary(direction.y, origin.y, cell.y),
+        next_boundary(direction.z, origin.z, cell.z),
+    );
+
+    let t_delta = Vec3::new(
+        (1.0 / direction.x).abs(),
+        (1.0 / direction.y).abs(),
+        (1.0 / direction.z).abs(),
+    );
+
+    let mut t_enter = 0.0;
+
+    while t_enter < max_distance {
+        let t_exit = t_max.min_element().min(max_distance);
+
+        // -- Look for a crossing inside the part of the ray in this cell
+        let mut previous = t_enter;
+
+        for i in 1..=CELL_SAMPLES {
+            let t = t_enter + (t_exit - t_enter) * i as f32 / CELL_SAMPLES as f32;
+
+            if solid(t) {
+                let t = refine(previous, t, &solid);
+                return Some(hit(origin, direction, t, &sample));
+            }
+
+            previous = t;
+        }
+
+        // -- Step into the next cell along the closest boundary
+        if t_max.x <= t_max.y && t_max.x <= t_max.z {
+            t_max.x += t_delta.x;
+        } else if t_max.y <= t_max.z {
+            t_max.y += t_delta.y;
+        } else {
+            t_max.z += t_delta.z;
+        }
+
+        t_enter = t_exit;
+    }
+
+    None
+}
+
+// -- Bisect between an empty and a solid point on the ray --
+fn refine(mut empty: f32, mut solid: f32, is_solid: &impl Fn(f32) -> bool) -> f32 {
+    for _ in 0..REFINE_STEPS {
+        let middle = (empty + solid) * 0.5;
+
+        if is_solid(middle) { solid = middle; } else { empty = middle; }
+    }
+
+    (empty + solid) * 0.5
+}
+
+fn hit(
+    origin: Vec3,
+    direction: Vec3,
+    distance: f32,
+    sample: &impl Fn(IVec3) -> Option<Voxel>,
+) -> VoxelHit {
+    let point = origin + direction * distance;
+
+    // -- The solid corner of the surrounding cell closest to the hit
+    let floor = point.floor().as_ivec3();
+    let mut closest: Option<(f32, IVec3, MaterialId)> = None;
+
+    for z in 0..=1 {
+        for y in 0..=1 {
+            for x in 0..=1 {
+                let voxel = floor + IVec3::new(x, y, z);
+
+                let material = match sample(voxel) {
+                    Some(sample) if sample.is_solid() => sample.material,
+                    _ => continue,
+                };
+
+                let distance = voxel.as_vec3().distance_squared(point);
+
+                if closest.map_or(true, |(best, _, _)| distance < best) {
+                    closest = Some((distance, voxel, material));
+                }
+            }
+        }
+    }
+
+    let (voxel, material) = closest
+        .map(|(_, voxel, material)| (voxel, material))
+        .unwrap_or((point.round().as_ivec3(), AIR));
+
+    VoxelHit {
+        point,
+        normal: normal_at(point, sample),
+        distance,
+        voxel,
+        material,
+        body: None,
+    }
+}
+
+// -- Cast from the camera along its look direction, at the terrain and any voxel bodies --
+pub fn update_target(
+    mut target: ResMut<VoxelTarget>,
+    manager: Res<ChunkManager>,
+    rapier_context: Res<RapierContext>,
+    chunks: Query<&Chunk>,
+    bodies: Query<(&GlobalTransform, &VoxelBody)>,
+    camera: Query<(&OrbitCamera, &Transform)>,
+) {
+    let (orbit_camera, transform) = match camera.iter().next() {
+        Some(camera) => camera,
+        None => return,
+    };
+
+    let origin = orbit_camera.camera_position;
+    let direction = transform.forward();
+
+    let terrain = raycast(
+        origin,
+        direction,
+        target.max_distance,
+        |world| manager.voxel(&chunks, world),
+    );
+
+    // -- Bodies move around, so they are found through their colliders
+    let is_body = |entity| bodies.get(entity).is_ok();
+    let filter = QueryFilter::default().predicate(&is_body);
+
+    let body = rapier_context
+        .cast_ray_and_get_normal(origin, direction, target.max_distance, true, filter)
+        .and_then(|(entity, hit)| {
+            let (body_transform, body) = bodies.get(entity).ok()?;
+
+            // -- Half a sample in from the surface is the sample that was hit
+            let inside = hit.point - hit.normal * 0.5;
+            let local = body_transform.compute_matrix().inverse().transform_point3(inside);
+
+            let voxel = local.round().as_ivec3();
+            let material = body.get(voxel)
+                .filter(|voxel| voxel.is_solid())
+                .map_or(AIR, |voxel| voxel.material);
+
+            Some(VoxelHit {
+                point: hit.point,
+                normal: hit.normal,
+                distance: hit.toi,
+                voxel,
+                material,
+                body: Some(entity),
+            })
+        });
+
+    target.hit = match (terrain, body) {
+        (Some(terrain), Some(body)) => Some(if body.distance < terrain.distance { body } else { terrain }),
+        (terrain, body) => terrain.or(body),
+    };
+}
@@ -0,0 +1,300 @@
+use bevy::prelude::*;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use super::{
+    chunk::{Chunk, CHUNK_SAMPLES},
+    fluid::{Fluid, FluidId},
+    material::MaterialId,
+};
+
+// -- Chunks along each axis of a region file --
+pub const REGION_SIZE: i32 = 8;
+
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const MAGIC: &[u8; 4] = b"PVRG";
+
+// -- Bump this whenever the layout below changes, version 1 had no fluid --
+pub const REGION_VERSION: u32 = 2;
+
+// -- Magic, version, then an (offset, length) pair per chunk --
+const HEADER_SIZE: usize = 8 + REGION_CHUNKS * 8;
+
+// -- Region coordinate that holds the given chunk --
+pub fn region_coord(chunk: IVec3) -> IVec3 {
+    IVec3::new(
+        chunk.x.div_euclid(REGION_SIZE),
+        chunk.y.div_euclid(REGION_SIZE),
+        chunk.z.div_euclid(REGION_SIZE),
+    )
+}
+
+fn slot(chunk: IVec3) -> usize {
+    let local = IVec3::new(
+        chunk.x.rem_euclid(REGION_SIZE),
+        chunk.y.rem_euclid(REGION_SIZE),
+        chunk.z.rem_euclid(REGION_SIZE),
+    );
+
+    (local.x + local.y * REGION_SIZE + local.z * REGION_SIZE * REGION_SIZE) as usize
+}
+
+/// A cube of REGION_SIZE chunks saved as one file. The header holds the
+/// offset and length of every chunk, followed by the chunks compressed one by
+/// one, so a changed chunk only has to be compressed again by itself. Chunks
+/// that were never modified are not stored and get generated instead.
+pub struct Region {
+    chunks: Vec<Option<Vec<u8>>>,
+
+    // -- Changed since it was last written
+    pub dirty: bool,
+}
+
+impl Default for Region {
+    fn default() -> Self {
+        Self {
+            chunks: vec![None; REGION_CHUNKS],
+            dirty: false,
+        }
+    }
+}
+
+impl Region {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err(invalid("not a region file"));
+        }
+
+        let version = read_u32(&bytes, 4);
+        if version == 0 || version > REGION_VERSION {
+            return Err(invalid(&format!("region version {} is not supported", version)));
+        }
+
+        let mut region = Self::default();
+
+        for (index, chunk) in region.chunks.iter_mut().enumerate() {
+            let entry = 8 + index * 8;
+            let offset = read_u32(&bytes, entry) as usize;
+            let length = read_u32(&bytes, entry + 4) as usize;
+
+            if length == 0 { continue; }
+
+            let data = bytes.get(offset..offset + length)
+                .ok_or_else(|| invalid("chunk runs past the end of the file"))?;
+
+            *chunk = Some(data.to_vec());
+        }
+
+        // -- Older regions are converted once, and written out again on the next save
+        if version < REGION_VERSION {
+            for chunk in region.chunks.iter_mut().flatten() {
+                *chunk = encode(&decode(IVec3::ZERO, chunk, version)?)?;
+            }
+
+            region.dirty = true;
+        }
+
+        Ok(region)
+    }
+
+    // -- Written next to the old file first, so a crash mid write keeps the old one --
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        let mut body = Vec::new();
+
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&REGION_VERSION.to_le_bytes());
+
+        for chunk in self.chunks.iter() {
+            let (offset, length) = match chunk {
+                Some(data) => {
+                    let offset = HEADER_SIZE + body.len();
+                    body.extend_from_slice(data);
+                    (offset as u32, data.len() as u32)
+                }
+                None => (0, 0),
+            };
+
+            header.extend_from_slice(&offset.to_le_bytes());
+            header.extend_from_slice(&length.to_le_bytes());
+        }
+
+        if let Some(parent) = path.parent() { fs::create_dir_all(parent)?; }
+
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, [header, body].concat())?;
+        fs::rename(&temp, path)
+    }
+
+    pub fn get(&self, position: IVec3) -> io::Result<Option<Chunk>> {
+        match &self.chunks[slot(position)] {
+            Some(data) => decode(position, data, REGION_VERSION).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set(&mut self, chunk: &Chunk) -> io::Result<()> {
+        self.chunks[slot(chunk.position)] = Some(encode(chunk)?);
+        self.dirty = true;
+        Ok(())
+    }
+}
+
+// -- Every density as little endian f32s, then every material id, fluid level
+// and fluid kind --
+fn encode(chunk: &Chunk) -> io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+
+    for density in chunk.densities() {
+        encoder.write_all(&density.to_le_bytes())?;
+    }
+
+    encoder.write_all(chunk.materials())?;
+    encoder.write_all(chunk.fluid_levels())?;
+    encoder.write_all(chunk.fluid_kinds())?;
+    encoder.finish()
+}
+
+fn decode(position: IVec3, data: &[u8], version: u32) -> io::Result<Chunk> {
+    let len = (CHUNK_SAMPLES * CHUNK_SAMPLES * CHUNK_SAMPLES) as usize;
+    let size = if version == 1 { len * 5 } else { len * 7 };
+
+    let mut bytes = Vec::with_capacity(size);
+    DeflateDecoder::new(data).read_to_end(&mut bytes)?;
+
+    if bytes.len() != size { return Err(invalid("chunk has the wrong number of samples")); }
+
+    let (density, rest) = bytes.split_at(len * 4);
+    let (material, fluid) = rest.split_at(len);
+
+    let density = density.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    let material: Vec<MaterialId> = material.to_vec();
+
+    let (fluid_level, fluid_kind): (Vec<u8>, Vec<FluidId>) = if fluid.is_empty() {
+        (vec![0; len], vec![Fluid::default().kind; len])
+    } else {
+        let (level, kind) = fluid.split_at(len);
+        (level.to_vec(), kind.to_vec())
+    };
+
+    Chunk::from_raw(position, density, material, fluid_level, fluid_kind)
+        .ok_or_else(|| invalid("chunk has the wrong number of samples"))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_engine::{chunk::{Voxel, CHUNK_SIZE}, fluid::WATER, material::{DIRT, STONE}};
+    use std::path::PathBuf;
+
+    // -- A file of its own in the temp dir, removed again when dropped --
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("region-test-{}-{}.region", name, std::process::id())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn edited_chunk(position: IVec3) -> Chunk {
+        let mut chunk = Chunk::new(position);
+
+        // -- A line through the padding on both sides, alternating materials
+        for x in -1..=CHUNK_SIZE + 1 {
+            let material = if x % 2 == 0 { STONE } else { DIRT };
+            chunk.set(IVec3::new(x, 3, x / 2), Voxel { density: x as f32 * 0.25 - 1.0, material });
+        }
+
+        chunk.set_fluid(IVec3::new(4, 5, 6), Fluid { level: 200, kind: WATER });
+        chunk
+    }
+
+    fn assert_same(a: &Chunk, b: &Chunk) {
+        let bits = |chunk: &Chunk| chunk.densities().iter().map(|density| density.to_bits()).collect::<Vec<u32>>();
+
+        assert_eq!(a.position, b.position);
+        assert_eq!(bits(a), bits(b));
+        assert_eq!(a.materials(), b.materials());
+        assert_eq!(a.fluid_levels(), b.fluid_levels());
+        assert_eq!(a.fluid_kinds(), b.fluid_kinds());
+    }
+
+    #[test]
+    fn chunks_round_trip() {
+        let file = TempFile::new("round-trip");
+        let position = IVec3::new(-3, 2, 17);
+        let chunk = edited_chunk(position);
+
+        let mut region = Region::default();
+        region.set(&chunk).unwrap();
+        region.write(&file.0).unwrap();
+
+        let read = Region::read(&file.0).unwrap();
+        assert!(!read.dirty);
+        assert_same(&read.get(position).unwrap().unwrap(), &chunk);
+
+        // -- Never stored, so it gets generated instead
+        assert!(read.get(position + IVec3::X).unwrap().is_none());
+    }
+
+    #[test]
+    fn version_1_is_upgraded() {
+        let file = TempFile::new("version-1");
+        let position = IVec3::new(9, -1, 0);
+        let chunk = edited_chunk(position);
+
+        // -- Version 1 stored the densities and materials only
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        for density in chunk.densities() { encoder.write_all(&density.to_le_bytes()).unwrap(); }
+        encoder.write_all(chunk.materials()).unwrap();
+        let data = encoder.finish().unwrap();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+
+        for index in 0..REGION_CHUNKS {
+            let (offset, length) = if index == slot(position) { (HEADER_SIZE as u32, data.len() as u32) } else { (0, 0) };
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&length.to_le_bytes());
+        }
+
+        bytes.extend_from_slice(&data);
+        fs::write(&file.0, bytes).unwrap();
+
+        // -- Same terrain, no fluid, and marked to be written in the new format
+        let read = Region::read(&file.0).unwrap();
+        assert!(read.dirty);
+
+        let mut dry = chunk.clone();
+        dry.set_fluid(IVec3::new(4, 5, 6), Fluid::default());
+        assert_same(&read.get(position).unwrap().unwrap(), &dry);
+
+        // -- And it reads back the same once written as version 2
+        read.write(&file.0).unwrap();
+        assert_same(&Region::read(&file.0).unwrap().get(position).unwrap().unwrap(), &dry);
+    }
+}
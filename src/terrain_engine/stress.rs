@@ -0,0 +1,310 @@
+use bevy::prelude::*;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+};
+
+use super::{
+    chunk::{Chunk, Voxel},
+    chunk_manager::ChunkManager,
+    connectivity::island_body,
+    edit::write_samples,
+    material::{self, MaterialId, STONE},
+    voxel_body,
+};
+
+// -- Stability of an anchored sample, in fixed point so the result does not
+// depend on float rounding --
+pub const STABLE: u32 = 1 << 20;
+
+const NEIGHBOURS: [IVec3; 6] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z];
+
+/// Edits waiting to have the terrain around them checked for overhangs that
+/// are too long for their material. Works like `Connectivity`, anything on
+/// the edge of the search box, below the bedrock level or unloaded is anchored.
+/// Areas are queued with `Connectivity::check`, and only get here once their
+/// detached islands have been removed.
+pub struct StressAnalysis {
+    pub search_margin: i32,
+    pub bedrock_level: i32,
+
+    // -- Failed sections smaller than this crumble away instead of becoming a body
+    pub min_body_samples: usize,
+
+    pub checks_per_frame: usize,
+
+    // -- Edited samples, min and max inclusive, handed on by `detach_islands`
+    pub pending: VecDeque<(IVec3, IVec3)>,
+}
+
+impl Default for StressAnalysis {
+    fn default() -> Self {
+        Self {
+            search_margin: 12,
+            bedrock_level: -64,
+            min_body_samples: 8,
+            checks_per_frame: 1,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+// -- Stability lost when `held` is carried by `holder`. Resting on top of
+// something is free, hanging below it costs twice as much as sideways --
+fn support_cost(holder: MaterialId, held: MaterialId, direction: IVec3) -> u32 {
+    if direction == IVec3::Y { return 0; }
+
+    let strength = material::strength(holder);
+    if strength <= 0.0 { return STABLE; }
+
+    let cost = (material::mass_density(held) / strength * STABLE as f32).round() as u32;
+
+    if direction == IVec3::NEG_Y { cost.saturating_mul(2) } else { cost }
+}
+
+/// Spreads stability out from the anchored samples between `min` and `max`,
+/// losing some for every sample held up sideways or from above, and returns
+/// each connected section of solid samples that ran out. Only sections with a
+/// sample inside `touched` are returned, so old overhangs the world generated
+/// do not all come down at once. Ties are broken by position, so the same
+/// samples always give the same result.
+pub fn find_failures(
+    min: IVec3,
+    max: IVec3,
+    touched: (IVec3, IVec3),
+    bedrock_level: i32,
+    sample: impl Fn(IVec3) -> Option<Voxel>,
+) -> Vec<Vec<IVec3>> {
+    let size = max - min + IVec3::ONE;
+    if size.min_element() <= 0 { return Vec::new(); }
+
+    let index = |p: IVec3| {
+        let local = p - min;
+        (local.x + local.y * size.x + local.z * size.x * size.y) as usize
+    };
+    let position = |i: usize| {
+        let i = i as i32;
+        min + IVec3::new(i % size.x, (i / size.x) % size.y, i / (size.x * size.y))
+    };
+    let inside = |p: IVec3| p.cmpge(min).all() && p.cmple(max).all();
+
+    let len = (size.x * size.y * size.z) as usize;
+    let mut materials: Vec<Option<MaterialId>> = vec![None; len];
+    let mut stability = vec![0_u32; len];
+    let mut heap = BinaryHeap::new();
+
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let p = IVec3::new(x, y, z);
+                let i = index(p);
+
+                let anchored = match sample(p) {
+                    Some(voxel) if voxel.is_solid() => {
+                        materials[i] = Some(voxel.material);
+                        p.cmpeq(min).any() || p.cmpeq(max).any() || p.y <= bedrock_level
+                    }
+                    Some(_) => continue,
+
+                    // -- Unloaded, assume solid ground
+                    None => {
+                        materials[i] = Some(STONE);
+                        true
+                    }
+                };
+
+                if anchored {
+                    stability[i] = STABLE;
+                    heap.push((STABLE, Reverse(i)));
+                }
+            }
+        }
+    }
+
+    // -- Most stable first, so every sample ends up with the best support it can get
+    while let Some((current, Reverse(i))) = heap.pop() {
+        if current < stability[i] { continue; }
+
+        let p = position(i);
+        let holder = materials[i].unwrap_or(STONE);
+
+        for direction in NEIGHBOURS {
+            let next = p + direction;
+            if !inside(next) { continue; }
+
+            let j = index(next);
+            let held = match materials[j] {
+                Some(held) => held,
+                None => continue,
+            };
+
+            let remaining = current.saturating_sub(support_cost(holder, held, direction));
+
+            if remaining > stability[j] {
+                stability[j] = remaining;
+                heap.push((remaining, Reverse(j)));
+            }
+        }
+    }
+
+    // -- Group the failed samples into sections
+    let failed = |i: usize| materials[i].is_some() && stability[i] == 0;
+    let mut grouped = vec![false; len];
+    let mut sections = Vec::new();
+    let mut queue = VecDeque::new();
+
+    let (from, to) = touched;
+
+    for z in min.z..=max.z {
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let i = index(IVec3::new(x, y, z));
+                if !failed(i) || grouped[i] { continue; }
+
+                grouped[i] = true;
+                queue.push_back(i);
+
+                let mut section = Vec::new();
+
+                while let Some(i) = queue.pop_front() {
+                    let p = position(i);
+                    section.push(p);
+
+                    for direction in NEIGHBOURS {
+                        let next = p + direction;
+                        if !inside(next) { continue; }
+
+                        let j = index(next);
+                        if failed(j) && !grouped[j] {
+                            grouped[j] = true;
+                            queue.push_back(j);
+                        }
+                    }
+                }
+
+                if section.iter().any(|p| p.cmpge(from).all() && p.cmple(to).all()) {
+                    sections.push(section);
+                }
+            }
+        }
+    }
+
+    sections
+}
+
+// -- Break off whatever an edit left hanging too far out --
+pub fn collapse(
+    mut commands: Commands,
+    mut analysis: ResMut<StressAnalysis>,
+    manager: Res<ChunkManager>,
+    mut chunks: ParamSet<(Query<&Chunk>, Query<&mut Chunk>)>,
+) {
+    for _ in 0..analysis.checks_per_frame {
+        let (from, to) = match analysis.pending.pop_front() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        let margin = IVec3::splat(analysis.search_margin);
+
+        let (sections, bodies) = {
+            let read = chunks.p0();
+            let sample = |world| manager.voxel(&read, world);
+
+            // -- One sample around the edit, whatever it was resting on may have gone
+            let touched = (from - IVec3::ONE, to + IVec3::ONE);
+            let sections = find_failures(from - margin, to + margin, touched, analysis.bedrock_level, &sample);
+
+            let bodies: Vec<(IVec3, voxel_body::VoxelBody)> = sections.iter()
+                .filter(|section| section.len() >= analysis.min_body_samples)
+                .map(|section| island_body(section, &sample))
+                .collect();
+
+            (sections, bodies)
+        };
+
+        let changed: Vec<(IVec3, Voxel)> = sections.iter()
+            .flatten()
+            .map(|world| (*world, Voxel::air()))
+            .collect();
+
+        write_samples(&mut commands, &manager, &mut chunks.p1(), &changed);
+
+        for (origin, body) in bodies {
+            voxel_body::spawn(
+                &mut commands,
+                body,
+                Transform::from_translation(origin.as_vec3()),
+                manager.material.clone(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terrain_engine::material::SAND;
+    use bevy::utils::HashMap;
+
+    const MIN: IVec3 = IVec3::ZERO;
+    const MAX: IVec3 = IVec3::new(20, 12, 10);
+
+    // -- A pillar standing on the bottom of the box, with a beam sticking out
+    // sideways from its top --
+    fn pillar_with_beam(material: MaterialId, beam: i32) -> HashMap<IVec3, Voxel> {
+        let mut solid = HashMap::default();
+
+        for y in 0..=6 {
+            solid.insert(IVec3::new(5, y, 5), Voxel { density: 1.0, material });
+        }
+
+        for x in 6..6 + beam {
+            solid.insert(IVec3::new(x, 6, 5), Voxel { density: 1.0, material });
+        }
+
+        solid
+    }
+
+    fn failures(solid: &HashMap<IVec3, Voxel>) -> Vec<Vec<IVec3>> {
+        find_failures(MIN, MAX, (MIN, MAX), -64, |p| Some(solid.get(&p).copied().unwrap_or_else(Voxel::air)))
+    }
+
+    #[test]
+    fn supported_pillar_is_stable() {
+        assert!(failures(&pillar_with_beam(SAND, 0)).is_empty());
+    }
+
+    #[test]
+    fn weak_cantilever_fails_past_its_reach() {
+        // -- Sand loses 1.6 / 2 of its stability per sample, so only the first one holds
+        let mut sections = failures(&pillar_with_beam(SAND, 6));
+        assert_eq!(sections.len(), 1);
+
+        let mut failed = sections.remove(0);
+        failed.sort_by_key(|p| p.x);
+
+        let expected: Vec<IVec3> = (7..12).map(|x| IVec3::new(x, 6, 5)).collect();
+        assert_eq!(failed, expected);
+    }
+
+    #[test]
+    fn stronger_material_holds() {
+        assert!(!failures(&pillar_with_beam(SAND, 6)).is_empty());
+        assert!(failures(&pillar_with_beam(STONE, 6)).is_empty());
+
+        // -- Stone gives up 2.6 / 30 per sample, which runs out on the twelfth
+        let sections = failures(&pillar_with_beam(STONE, 13));
+        assert_eq!(sections.len(), 1);
+        assert!(sections[0].iter().all(|p| p.x >= 6 + 11));
+    }
+
+    #[test]
+    fn untouched_failures_are_left_alone() {
+        let solid = pillar_with_beam(SAND, 6);
+        let touched = (IVec3::new(0, 0, 0), IVec3::new(3, 3, 3));
+
+        let sections = find_failures(MIN, MAX, touched, -64, |p| Some(solid.get(&p).copied().unwrap_or_else(Voxel::air)));
+        assert!(sections.is_empty());
+    }
+}
@@ -0,0 +1,445 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::{Collider, ColliderMassProperties, MassProperties, RigidBody, Velocity};
+
+use super::{
+    chunk::{self, mesher, Voxel, ISO_LEVEL},
+    chunk_manager::ChunkManager,
+    connectivity,
+    edit::{edit_samples, BrushMode, VoxelEdit},
+    fluid::buoyancy::Buoyancy,
+    material,
+};
+
+/// A small grid of voxels that moves on its own as a rigid body, for boulders,
+/// debris and anything built by the player. Sample (0, 0, 0) sits at the
+/// origin of the entity, and it is meshed, given a collider and mass the same
+/// way whenever it is marked with `VoxelBodyDirty`.
+#[derive(Component, Clone, Debug)]
+pub struct VoxelBody {
+    dims: UVec3,
+    voxels: Vec<Voxel>,
+}
+
+// -- The body changed and needs a new mesh, collider and mass --
+#[derive(Component, Default, Clone, Debug)]
+pub struct VoxelBodyDirty;
+
+impl VoxelBody {
+    // -- Samples x first, then y, then z --
+    pub fn from_voxels(dims: UVec3, voxels: Vec<Voxel>) -> Self {
+        assert_eq!(voxels.len(), (dims.x * dims.y * dims.z) as usize);
+        Self { dims, voxels }
+    }
+
+    pub fn dims(&self) -> UVec3 {
+        self.dims
+    }
+
+    fn index(&self, local: IVec3) -> Option<usize> {
+        if local.cmplt(IVec3::ZERO).any() || local.cmpge(self.dims.as_ivec3()).any() { return None; }
+
+        Some(mesher::sample_index(self.dims, local.x as u32, local.y as u32, local.z as u32))
+    }
+
+    pub fn get(&self, local: IVec3) -> Option<Voxel> {
+        self.index(local).map(|index| self.voxels[index])
+    }
+
+    // -- Returns false if the sample is outside the grid --
+    pub fn set(&mut self, local: IVec3, voxel: Voxel) -> bool {
+        match self.index(local) {
+            Some(index) => { self.voxels[index] = voxel; true },
+            None => false,
+        }
+    }
+
+    pub fn mesh(&self) -> mesher::MeshData {
+        let densities: Vec<f32> = self.voxels.iter().map(|voxel| voxel.density).collect();
+        mesher::march(&densities, self.dims, ISO_LEVEL)
+    }
+
+    /// Treats every solid sample as a unit cube of its material, None when
+    /// nothing in the grid has any mass.
+    pub fn mass_properties(&self) -> Option<MassProperties> {
+        let cubes: Vec<(Vec3, f32)> = self.voxels.iter()
+            .enumerate()
+            .filter(|(_, voxel)| voxel.is_solid())
+            .map(|(index, voxel)| (self.position(index), material::mass_density(voxel.material)))
+            .filter(|(_, mass)| *mass > 0.0)
+            .collect();
+
+        let mass: f32 = cubes.iter().map(|(_, mass)| mass).sum();
+        if mass <= 0.0 { return None; }
+
+        let center = cubes.iter().map(|(position, mass)| *position * *mass).sum::<Vec3>() / mass;
+
+        // -- Point masses around the center, plus each cubes own inertia (m / 6),
+        // products of inertia included so lopsided shapes tumble right
+        let tensor = cubes.iter().fold(Mat3::ZERO, |tensor, (position, mass)| {
+            let d = *position - center;
+            let outer = Mat3::from_cols(d * d.x, d * d.y, d * d.z);
+
+            tensor + (Mat3::IDENTITY * (d.length_squared() + 1.0 / 6.0) - outer) * *mass
+        });
+
+        let (frame, inertia) = principal_axes(tensor);
+
+        Some(MassProperties {
+            local_center_of_mass: center,
+            mass,
+            principal_inertia_local_frame: frame,
+            principal_inertia: inertia,
+        })
+    }
+
+    // -- Number of solid samples with any mass, each one is a unit cube --
+    pub fn volume(&self) -> f32 {
+        self.voxels.iter()
+            .filter(|voxel| voxel.is_solid() && material::mass_density(voxel.material) > 0.0)
+            .count() as f32
+    }
+
+    fn position(&self, index: usize) -> Vec3 {
+        let index = index as u32;
+        let x = index % self.dims.x;
+        let y = (index / self.dims.x) % self.dims.y;
+        let z = index / (self.dims.x * self.dims.y);
+
+        Vec3::new(x as f32, y as f32, z as f32)
+    }
+
+    /// Applies a brush given in the bodies local space. Anything a brush adds
+    /// past the edge of the grid grows it, digging outside the grid has
+    /// nothing to remove. Returns None if nothing changed, otherwise the old
+    /// local position of the new (0, 0, 0) sample, which the entity has to be
+    /// moved to so the samples stay where they were.
+    pub fn apply_edit(&mut self, edit: &VoxelEdit) -> Option<IVec3> {
+        let outside = (edit.mode != BrushMode::Dig).then(Voxel::air);
+        let changed = edit_samples(edit, |local| self.get(local).or(outside));
+
+        if changed.is_empty() { return None; }
+
+        // -- With a sample of air past anything new, so the surface closes
+        let (mut min, mut max) = (IVec3::ZERO, self.dims.as_ivec3() - IVec3::ONE);
+
+        for (local, _) in changed.iter().filter(|(local, _)| self.get(*local).is_none()) {
+            min = min.min(*local - IVec3::ONE);
+            max = max.max(*local + IVec3::ONE);
+        }
+
+        self.grow(min, max);
+
+        for (local, voxel) in changed.iter() {
+            self.set(*local - min, *voxel);
+        }
+
+        Some(min)
+    }
+
+    // -- Resize the grid to cover min to max (inclusive) in the current local
+    // space, min becomes the new (0, 0, 0) and new samples are air --
+    fn grow(&mut self, min: IVec3, max: IVec3) {
+        let dims = (max - min + IVec3::ONE).as_uvec3();
+        if min == IVec3::ZERO && dims == self.dims { return; }
+
+        let mut grown = Self::from_voxels(dims, vec![Voxel::air(); (dims.x * dims.y * dims.z) as usize]);
+
+        for (index, voxel) in self.voxels.iter().enumerate() {
+            grown.set(self.position(index).as_ivec3() - min, *voxel);
+        }
+
+        *self = grown;
+    }
+
+    /// Every separate piece of the body, each with the local position of its
+    /// own (0, 0, 0) sample. Empty while the body is still in one piece.
+    pub fn split(&self) -> Vec<(IVec3, VoxelBody)> {
+        // -- Nothing is anchored, the samples around the grid are air
+        let sample = |local| Some(self.get(local).unwrap_or_else(Voxel::air));
+        let islands = connectivity::find_islands(-IVec3::ONE, self.dims.as_ivec3(), i32::MIN, sample);
+
+        if islands.len() < 2 { return Vec::new(); }
+
+        islands.iter()
+            .map(|island| connectivity::island_body(island, sample))
+            .collect()
+    }
+}
+
+/// Diagonalizes a symmetric inertia tensor with Jacobi rotations. Returns the
+/// rotation from the principal axes to the local frame, and the inertia along
+/// each of them.
+pub fn principal_axes(tensor: Mat3) -> (Quat, Vec3) {
+    let mut a = tensor.to_cols_array_2d();
+    let mut v = Mat3::IDENTITY.to_cols_array_2d();
+
+    // -- Column major, so a[column][row], symmetric either way
+    let scale = a[0][0].abs() + a[1][1].abs() + a[2][2].abs();
+
+    for _ in 0..32 {
+        let off = a[1][0] * a[1][0] + a[2][0] * a[2][0] + a[2][1] * a[2][1];
+        if off <= (scale * f32::EPSILON).powi(2) { break; }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[q][p] == 0.0 { continue; }
+
+            // -- The rotation that zeroes a[q][p]
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[q][p]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            for k in 0..3 {
+                let (kp, kq) = (a[p][k], a[q][k]);
+                a[p][k] = c * kp - s * kq;
+                a[q][k] = s * kp + c * kq;
+
+                let (kp, kq) = (v[p][k], v[q][k]);
+                v[p][k] = c * kp - s * kq;
+                v[q][k] = s * kp + c * kq;
+            }
+
+            for k in 0..3 {
+                let (pk, qk) = (a[k][p], a[k][q]);
+                a[k][p] = c * pk - s * qk;
+                a[k][q] = s * pk + c * qk;
+            }
+        }
+    }
+
+    // -- The eigenvectors are the columns, flip one if they came out left handed
+    let mut axes = Mat3::from_cols_array_2d(&v);
+    if axes.determinant() < 0.0 { axes.z_axis = -axes.z_axis; }
+
+    (Quat::from_mat3(&axes).normalize(), Vec3::new(a[0][0], a[1][1], a[2][2]))
+}
+
+// -- Convex pieces of the mesh, a single trimesh would not collide with the terrain --
+pub fn convex_collider(data: &mesher::MeshData) -> Option<Collider> {
+    if data.is_empty() { return None; }
+
+    let vertices: Vec<Vec3> = data.positions.iter().map(|position| Vec3::from(*position)).collect();
+    let indices: Vec<[u32; 3]> = data.indices.chunks(3).map(|i| [i[0], i[1], i[2]]).collect();
+
+    Some(Collider::convex_decomposition(&vertices, &indices))
+}
+
+pub fn spawn(
+    commands: &mut Commands,
+    body: VoxelBody,
+    transform: Transform,
+    material: Handle<StandardMaterial>,
+) -> Entity {
+    commands.spawn_bundle(PbrBundle {
+        transform,
+        material,
+        ..default()
+    })
+    .insert(body)
+    .insert(VoxelBodyDirty)
+    .insert(RigidBody::Dynamic)
+    .insert(Velocity::default())
+    .id()
+}
+
+// -- Run the same brushes the terrain gets over every body they reach, and
+// break up the ones that were dug in two --
+pub fn apply_edits(
+    mut commands: Commands,
+    mut edits: EventReader<VoxelEdit>,
+    mut bodies: Query<(Entity, &mut Transform, &mut VoxelBody, &Velocity, &Handle<StandardMaterial>)>,
+) {
+    let mut edited = Vec::new();
+
+    for edit in edits.iter() {
+        for (entity, mut transform, mut body, _, _) in bodies.iter_mut() {
+            let to_local = transform.compute_matrix().inverse();
+            let center = to_local.transform_point3(edit.center);
+
+            // -- Skip bodies the brush cannot reach
+            let reach = Vec3::splat(edit.radius + 1.0);
+            let size = body.dims().as_vec3();
+            if (center + reach).cmplt(Vec3::ZERO).any() || (center - reach).cmpgt(size).any() { continue; }
+
+            let local = VoxelEdit { center, ..edit.clone() };
+
+            let offset = match body.apply_edit(&local) {
+                Some(offset) => offset,
+                None => continue,
+            };
+
+            // -- The grid grew on the negative side
+            if offset != IVec3::ZERO {
+                transform.translation = transform.mul_vec3(offset.as_vec3());
+            }
+
+            commands.entity(entity).insert(VoxelBodyDirty);
+            if !edited.contains(&entity) { edited.push(entity); }
+        }
+    }
+
+    for entity in edited {
+        let (_, transform, body, velocity, material) = match bodies.get(entity) {
+            Ok(body) => body,
+            Err(_) => continue,
+        };
+
+        let pieces = body.split();
+        if pieces.is_empty() { continue; }
+
+        // -- Each piece carries on the way the whole body was moving
+        commands.entity(entity).despawn_recursive();
+
+        for (origin, piece) in pieces {
+            let piece_transform = Transform { translation: transform.mul_vec3(origin.as_vec3()), ..*transform };
+            let piece = spawn(&mut commands, piece, piece_transform, material.clone());
+            commands.entity(piece).insert(*velocity);
+        }
+    }
+}
+
+pub fn mesh_bodies(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    bodies: Query<(Entity, &VoxelBody), With<VoxelBodyDirty>>,
+) {
+    for (entity, body) in bodies.iter() {
+        let data = body.mesh();
+
+        let (collider, mass) = match (convex_collider(&data), body.mass_properties()) {
+            (Some(collider), Some(mass)) => (collider, mass),
+
+            // -- Dug away completely
+            _ => {
+                commands.entity(entity).despawn_recursive();
+                continue;
+            }
+        };
+
+        // -- Floats if it is lighter than the fluid, the grid has a sample of air around it
+        let buoyancy = Buoyancy {
+            density: mass.mass / body.volume().max(1.0),
+            center: mass.local_center_of_mass,
+            half_height: (body.dims().y as f32 - 2.0).max(1.0) * 0.5,
+        };
+
+        commands.entity(entity)
+            .insert(meshes.add(data.into_mesh()))
+            .insert(collider)
+            .insert(ColliderMassProperties::MassProperties(mass))
+            .insert(buoyancy)
+            .remove::<VoxelBodyDirty>();
+    }
+}
+
+// -- Bodies go with the chunk their center is in, so they do not pile up out of
+// view or fall through terrain that is not loaded --
+pub fn unload_bodies(
+    mut commands: Commands,
+    manager: Res<ChunkManager>,
+    bodies: Query<(Entity, &Transform, &VoxelBody)>,
+) {
+    for (entity, transform, body) in bodies.iter() {
+        let center = transform.mul_vec3(body.dims().as_vec3() * 0.5);
+        let position = chunk::chunk_coord(chunk::world_to_voxel(center));
+
+        if manager.get(position).is_none() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rebuild(frame: Quat, inertia: Vec3) -> Mat3 {
+        let axes = Mat3::from_quat(frame);
+        axes * Mat3::from_diagonal(inertia) * axes.transpose()
+    }
+
+    fn assert_close(a: Mat3, b: Mat3) {
+        let (a, b) = (a.to_cols_array(), b.to_cols_array());
+
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a - b).abs() < 1e-4, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn principal_axes_rebuild_the_tensor() {
+        let tensor = Mat3::from_cols(
+            Vec3::new(4.0, -1.0, 0.5),
+            Vec3::new(-1.0, 3.0, -0.25),
+            Vec3::new(0.5, -0.25, 5.0),
+        );
+
+        let (frame, inertia) = principal_axes(tensor);
+        assert_close(rebuild(frame, inertia), tensor);
+    }
+
+    #[test]
+    fn diagonal_pair_has_products_of_inertia() {
+        // -- Two cubes at opposite corners of a 2x2x1 grid
+        let stone = Voxel { density: 1.0, material: material::STONE };
+        let body = VoxelBody::from_voxels(UVec3::new(2, 2, 1), vec![stone, Voxel::air(), Voxel::air(), stone]);
+
+        let properties = body.mass_properties().unwrap();
+        let m = material::mass_density(material::STONE);
+
+        assert!((properties.local_center_of_mass - Vec3::new(0.5, 0.5, 0.0)).length() < 1e-5);
+
+        let expected = Mat3::from_cols(
+            Vec3::new(m / 2.0 + m / 3.0, -m / 2.0, 0.0),
+            Vec3::new(-m / 2.0, m / 2.0 + m / 3.0, 0.0),
+            Vec3::new(0.0, 0.0, m + m / 3.0),
+        );
+        assert_close(rebuild(properties.principal_inertia_local_frame, properties.principal_inertia), expected);
+
+        // -- Easiest to spin around the line through both cubes
+        let mut inertia = properties.principal_inertia.to_array();
+        inertia.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!((inertia[0] - m / 3.0).abs() < 1e-4);
+    }
+
+    fn solid_bar(length: u32) -> VoxelBody {
+        let stone = Voxel { density: 1.0, material: material::STONE };
+        let dims = UVec3::new(length + 2, 3, 3);
+
+        let voxels = (0..dims.x * dims.y * dims.z)
+            .map(|index| {
+                let (x, y, z) = (index % dims.x, (index / dims.x) % dims.y, index / (dims.x * dims.y));
+                if x == 0 || x == dims.x - 1 || y != 1 || z != 1 { Voxel::air() } else { stone }
+            })
+            .collect();
+
+        VoxelBody::from_voxels(dims, voxels)
+    }
+
+    #[test]
+    fn fill_past_the_edge_grows_the_grid() {
+        let mut body = solid_bar(3);
+        let before = body.get(IVec3::new(2, 1, 1)).unwrap();
+
+        let offset = body.apply_edit(&VoxelEdit::fill(Vec3::new(-1.0, 1.0, 1.0), 1.0, material::DIRT)).unwrap();
+        assert!(offset.cmplt(IVec3::ZERO).all());
+
+        // -- Old samples moved by the offset, the fill is in and has air around it
+        assert_eq!(body.get(IVec3::new(2, 1, 1) - offset), Some(before));
+        assert!(body.get(IVec3::new(-1, 1, 1) - offset).unwrap().is_solid());
+        assert!(!body.get(IVec3::ZERO).unwrap().is_solid());
+        assert!(body.dims().cmpgt(solid_bar(3).dims()).any());
+    }
+
+    #[test]
+    fn digging_through_splits_the_body() {
+        let mut body = solid_bar(5);
+        assert!(body.split().is_empty());
+
+        assert_eq!(body.apply_edit(&VoxelEdit::dig(Vec3::new(3.0, 1.0, 1.0), 0.5)), Some(IVec3::ZERO));
+
+        let pieces = body.split();
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces.iter().map(|(_, piece)| piece.volume()).sum::<f32>(), 4.0);
+    }
+}
@@ -0,0 +1,297 @@
+use bevy::{app::AppExit, prelude::*, utils::{HashMap, HashSet}};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::{
+    components::{CameraMode, OrbitCamera, Player},
+    config,
+};
+use super::{
+    chunk::Chunk,
+    chunk_manager::ChunkModified,
+    generator::GENERATOR_VERSION,
+    region::{self, Region},
+};
+
+pub const WORLD_FILE: &str = "world.ron";
+pub const DEFAULT_WORLD: &str = "world";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraMeta {
+    pub horizontal_angle: f32,
+    pub vertical_angle: f32,
+    pub camera_step: u32,
+}
+
+impl Default for CameraMeta {
+    fn default() -> Self {
+        Self {
+            horizontal_angle: 0.0,
+            vertical_angle: 0.0,
+            camera_step: OrbitCamera::default().camera_step,
+        }
+    }
+}
+
+/// Everything about a world that is not in its chunks, kept in world.ron. The
+/// seed and generator version are required, without them the saved chunks
+/// would end up surrounded by a different world.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WorldMeta {
+    pub seed: u32,
+
+    // -- The generator the saved chunks were made with, unsaved chunks are
+    // generated again so they will not line up if this changed
+    pub generator_version: u32,
+
+    // -- None until the world has been saved once, the player then spawns on the terrain
+    #[serde(default)]
+    pub player_position: Option<Vec3>,
+
+    #[serde(default)]
+    pub camera: CameraMeta,
+}
+
+impl WorldMeta {
+    // -- A world that has never been saved, with a random seed --
+    pub fn random() -> Self {
+        Self {
+            seed: rand::random(),
+            generator_version: GENERATOR_VERSION,
+            player_position: None,
+            camera: CameraMeta::default(),
+        }
+    }
+}
+
+/// The world being played, in <user data dir>/PhysicalVoxel/worlds/<name>.
+/// Only chunks that were edited get saved, grouped into region files that are
+/// kept in memory while any of their chunks are loaded, and written out on
+/// autosave, exit and when they are dropped.
+pub struct WorldSave {
+    pub name: String,
+    pub dir: PathBuf,
+    pub meta: WorldMeta,
+
+    pub autosave: Timer,
+
+    regions: HashMap<IVec3, Region>,
+}
+
+impl WorldSave {
+    // -- Err when world.ron exists but can not be read, starting over would
+    // overwrite it and generate a different world around the saved chunks --
+    pub fn open(name: &str) -> Result<Self, String> {
+        let dir = config::data_dir().join("worlds").join(name);
+        let path = dir.join(WORLD_FILE);
+
+        let meta = match fs::read_to_string(&path) {
+            Ok(contents) => ron::from_str::<WorldMeta>(&contents)
+                .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                info!("Creating world {} in {}", name, dir.display());
+                WorldMeta::random()
+            }
+            Err(err) => return Err(format!("Failed to read {}: {}", path.display(), err)),
+        };
+
+        if meta.generator_version != GENERATOR_VERSION {
+            warn!(
+                "World {} was made with generator version {}, this is version {}. Unsaved terrain will not match",
+                name, meta.generator_version, GENERATOR_VERSION,
+            );
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            dir,
+            meta,
+            autosave: Timer::from_seconds(60.0, true),
+            regions: HashMap::default(),
+        })
+    }
+
+    // -- Read the region from disk the first time it is needed --
+    fn region(&mut self, position: IVec3) -> &mut Region {
+        let region = region::region_coord(position);
+        let path = region_path(&self.dir, region);
+
+        self.regions.entry(region).or_insert_with(|| {
+            if !path.exists() { return Region::default(); }
+
+            Region::read(&path).unwrap_or_else(|err| {
+                error!("Failed to read {}, its chunks will be generated again: {}", path.display(), err);
+                Region::default()
+            })
+        })
+    }
+
+    // -- The saved chunk at this position, None if it was never edited --
+    pub fn load_chunk(&mut self, position: IVec3) -> Option<Chunk> {
+        self.region(position).get(position).unwrap_or_else(|err| {
+            error!("Failed to load chunk {}: {}", position, err);
+            None
+        })
+    }
+
+    pub fn store_chunk(&mut self, chunk: &Chunk) {
+        if let Err(err) = self.region(chunk.position).set(chunk) {
+            error!("Failed to store chunk {}: {}", chunk.position, err);
+        }
+    }
+
+    // -- Write out and forget the regions none of the loaded chunks are in. One
+    // that fails to write is kept, so its chunks are not lost --
+    pub fn release_regions(&mut self, loaded: impl Iterator<Item = IVec3>) {
+        let used: HashSet<IVec3> = loaded.map(region::region_coord).collect();
+        let dir = self.dir.clone();
+
+        self.regions.retain(|position, region| {
+            if used.contains(position) { return true; }
+            if !region.dirty { return false; }
+
+            let path = region_path(&dir, *position);
+
+            match region.write(&path) {
+                Ok(()) => false,
+                Err(err) => {
+                    error!("Failed to write {}: {}", path.display(), err);
+                    true
+                }
+            }
+        });
+    }
+
+    // -- Write every region that changed and the metadata --
+    pub fn flush(&mut self) {
+        for (position, region) in self.regions.iter_mut() {
+            if !region.dirty { continue; }
+
+            let path = region_path(&self.dir, *position);
+
+            match region.write(&path) {
+                Ok(()) => region.dirty = false,
+                Err(err) => error!("Failed to write {}: {}", path.display(), err),
+            }
+        }
+
+        let path = self.dir.join(WORLD_FILE);
+
+        let contents = match ron::ser::to_string_pretty(&self.meta, ron::ser::PrettyConfig::default()) {
+            Ok(contents) => contents,
+            Err(err) => {
+                error!("Failed to serialize {}: {}", path.display(), err);
+                return;
+            }
+        };
+
+        if let Err(err) = fs::create_dir_all(&self.dir).and_then(|_| fs::write(&path, contents)) {
+            error!("Failed to write {}: {}", path.display(), err);
+        }
+    }
+}
+
+fn region_path(dir: &Path, region: IVec3) -> PathBuf {
+    dir.join("regions").join(format!("r.{}.{}.{}.region", region.x, region.y, region.z))
+}
+
+// -- `--world <name>` picks the world, anything that is not a plain name is ignored --
+pub fn world_name() -> String {
+    let args: Vec<String> = std::env::args().collect();
+
+    let name = args.iter()
+        .position(|arg| arg == "--world")
+        .and_then(|index| args.get(index + 1));
+
+    match name {
+        Some(name) if is_valid_name(name) => name.clone(),
+        Some(name) => {
+            warn!("World name {:?} can only use letters, numbers, - and _, using {}", name, DEFAULT_WORLD);
+            DEFAULT_WORLD.to_string()
+        }
+        None => DEFAULT_WORLD.to_string(),
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// -- Put the player and camera back where they were when the world was saved --
+pub fn restore_player(
+    save: Res<WorldSave>,
+    mut players: Query<&mut Transform, Added<Player>>,
+    mut cameras: Query<&mut OrbitCamera, Added<OrbitCamera>>,
+) {
+    if let Some(position) = save.meta.player_position {
+        for mut transform in players.iter_mut() {
+            transform.translation = position;
+        }
+    }
+
+    let camera = &save.meta.camera;
+
+    for mut orbit_camera in cameras.iter_mut() {
+        orbit_camera.horizontal_angle = camera.horizontal_angle;
+        orbit_camera.vertical_angle = camera.vertical_angle;
+        orbit_camera.target_horizontal_angle = camera.horizontal_angle;
+        orbit_camera.target_vertical_angle = camera.vertical_angle;
+        orbit_camera.camera_step = camera.camera_step.min(orbit_camera.camera_step_max);
+
+        if orbit_camera.camera_step == 0 {
+            orbit_camera.camera_mode = CameraMode::FirstPerson;
+            orbit_camera.first_person_blend = 1.0;
+        }
+    }
+}
+
+// -- Save the edited chunks every so often and when the game closes --
+pub fn autosave(
+    mut commands: Commands,
+    mut save: ResMut<WorldSave>,
+    mut exit: EventReader<AppExit>,
+    time: Res<Time>,
+    chunks: Query<(Entity, &Chunk), With<ChunkModified>>,
+    players: Query<&Transform, With<Player>>,
+    cameras: Query<&OrbitCamera>,
+) {
+    let exiting = exit.iter().next().is_some();
+    let due = save.autosave.tick(time.delta()).just_finished();
+
+    if !exiting && !due { return; }
+
+    for (entity, chunk) in chunks.iter() {
+        save.store_chunk(chunk);
+        commands.entity(entity).remove::<ChunkModified>();
+    }
+
+    if let Some(transform) = players.iter().next() {
+        save.meta.player_position = Some(transform.translation);
+    }
+
+    if let Some(orbit_camera) = cameras.iter().next() {
+        save.meta.camera = CameraMeta {
+            horizontal_angle: orbit_camera.horizontal_angle,
+            vertical_angle: orbit_camera.vertical_angle,
+            camera_step: orbit_camera.camera_step,
+        };
+    }
+
+    save.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seed_is_required() {
+        assert!(ron::from_str::<WorldMeta>("(generator_version: 1)").is_err());
+        assert!(ron::from_str::<WorldMeta>("(sed: 5, generator_version: 1)").is_err());
+
+        let meta = ron::from_str::<WorldMeta>("(seed: 5, generator_version: 1)").unwrap();
+        assert_eq!(meta.seed, 5);
+        assert!(meta.player_position.is_none());
+    }
+}
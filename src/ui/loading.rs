@@ -0,0 +1,37 @@
+use bevy::prelude::*;
+
+use crate::state::LoadingProgress;
+use super::{spawn_screen, text, FONT};
+
+#[derive(Component, Default)]
+pub struct LoadingText;
+
+pub fn spawn(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let font = asset_server.load(FONT);
+
+    spawn_screen(&mut commands, Color::rgb(0.05, 0.05, 0.07))
+        .with_children(|screen| {
+            screen.spawn_bundle(text("PhysicalVoxel", font.clone(), 48.0));
+            screen.spawn_bundle(text("", font, 24.0)).insert(LoadingText);
+        });
+}
+
+pub fn update(
+    progress: Res<LoadingProgress>,
+    mut texts: Query<&mut Text, With<LoadingText>>,
+) {
+    if !progress.is_changed() { return; }
+
+    let status = if !progress.pipeline_ready {
+        "Compiling shaders...".to_string()
+    } else {
+        format!("Generating terrain {} / {}", progress.meshed, progress.total)
+    };
+
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = status.clone();
+    }
+}
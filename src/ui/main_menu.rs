@@ -0,0 +1,44 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::state::GameState;
+use super::{button, spawn_screen, text, FONT};
+
+#[derive(Component, Clone, Copy)]
+pub enum MainMenuButton {
+    Play,
+    Quit,
+}
+
+pub fn spawn(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let font = asset_server.load(FONT);
+
+    spawn_screen(&mut commands, Color::rgba(0.0, 0.0, 0.0, 0.5))
+        .with_children(|screen| {
+            screen.spawn_bundle(text("PhysicalVoxel", font.clone(), 48.0));
+            button(screen, "Play", font.clone(), MainMenuButton::Play);
+            button(screen, "Quit", font, MainMenuButton::Quit);
+        });
+}
+
+pub fn buttons(
+    mut state: ResMut<State<GameState>>,
+    mut exit: EventWriter<AppExit>,
+    keys: Res<Input<KeyCode>>,
+    buttons: Query<(&Interaction, &MainMenuButton), Changed<Interaction>>,
+) {
+    if keys.just_pressed(KeyCode::Return) {
+        let _ = state.set(GameState::InGame);
+    }
+
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Clicked { continue; }
+
+        match button {
+            MainMenuButton::Play => { let _ = state.set(GameState::InGame); },
+            MainMenuButton::Quit => exit.send(AppExit),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use bevy::{ecs::system::EntityCommands, prelude::*};
+
+use crate::state::GameState;
+
+pub mod loading;
+pub mod main_menu;
+pub mod pause_menu;
+
+pub const FONT: &str = "fonts/DejaVuSans.ttf";
+
+pub const BUTTON_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const BUTTON_HOVER_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const BUTTON_PRESSED_COLOR: Color = Color::rgb(0.35, 0.45, 0.35);
+
+// -- Root node of a full screen of UI, despawned when its state is left --
+#[derive(Component, Default)]
+pub struct Screen;
+
+pub struct UiPlugin;
+
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(button_colors);
+
+        app.add_system_set(SystemSet::on_enter(GameState::Loading).with_system(loading::spawn));
+        app.add_system_set(SystemSet::on_update(GameState::Loading).with_system(loading::update));
+        app.add_system_set(SystemSet::on_exit(GameState::Loading).with_system(despawn_screens));
+
+        app.add_system_set(SystemSet::on_enter(GameState::MainMenu).with_system(main_menu::spawn));
+        app.add_system_set(SystemSet::on_update(GameState::MainMenu).with_system(main_menu::buttons));
+        app.add_system_set(SystemSet::on_exit(GameState::MainMenu).with_system(despawn_screens));
+
+        app.add_system_set(SystemSet::on_enter(GameState::Paused).with_system(pause_menu::spawn));
+        app.add_system_set(
+            SystemSet::on_update(GameState::Paused)
+                .with_system(pause_menu::buttons)
+                .with_system(pause_menu::setting_buttons)
+                .with_system(pause_menu::setting_values)
+        );
+        app.add_system_set(SystemSet::on_exit(GameState::Paused).with_system(despawn_screens));
+    }
+}
+
+pub fn despawn_screens(
+    mut commands: Commands,
+    screens: Query<Entity, With<Screen>>,
+) {
+    for entity in screens.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn button_colors(
+    mut buttons: Query<(&Interaction, &mut UiColor), (Changed<Interaction>, With<Button>)>,
+) {
+    for (interaction, mut color) in buttons.iter_mut() {
+        *color = match interaction {
+            Interaction::Clicked => BUTTON_PRESSED_COLOR,
+            Interaction::Hovered => BUTTON_HOVER_COLOR,
+            Interaction::None => BUTTON_COLOR,
+        }.into();
+    }
+}
+
+// -- Full screen column with everything centered, children go top to bottom --
+pub fn spawn_screen<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    background: Color,
+) -> EntityCommands<'w, 's, 'a> {
+    let mut screen = commands.spawn_bundle(NodeBundle {
+        style: Style {
+            size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::ColumnReverse,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: background.into(),
+        ..default()
+    });
+
+    screen.insert(Screen);
+    screen
+}
+
+pub fn text(value: impl Into<String>, font: Handle<Font>, font_size: f32) -> TextBundle {
+    TextBundle::from_section(value, TextStyle {
+        font,
+        font_size,
+        color: Color::WHITE,
+    })
+    .with_style(Style {
+        margin: UiRect::all(Val::Px(8.0)),
+        ..default()
+    })
+}
+
+// -- A labelled button, `marker` is what the click handlers query for --
+pub fn button(
+    parent: &mut ChildBuilder,
+    label: &str,
+    font: Handle<Font>,
+    marker: impl Component,
+) {
+    sized_button(parent, Size::new(Val::Px(240.0), Val::Px(48.0)), label, font, marker);
+}
+
+// -- Square button for +/- and the like --
+pub fn small_button(
+    parent: &mut ChildBuilder,
+    label: &str,
+    font: Handle<Font>,
+    marker: impl Component,
+) {
+    sized_button(parent, Size::new(Val::Px(48.0), Val::Px(48.0)), label, font, marker);
+}
+
+fn sized_button(
+    parent: &mut ChildBuilder,
+    size: Size<Val>,
+    label: &str,
+    font: Handle<Font>,
+    marker: impl Component,
+) {
+    parent.spawn_bundle(ButtonBundle {
+        style: Style {
+            size,
+            margin: UiRect::all(Val::Px(6.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: BUTTON_COLOR.into(),
+        ..default()
+    })
+    .insert(marker)
+    .with_children(|button| {
+        button.spawn_bundle(text(label, font, 24.0));
+    });
+}
@@ -0,0 +1,223 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{settings::Settings, state::GameState};
+use super::{button, small_button, spawn_screen, text, Screen, BUTTON_COLOR, FONT};
+
+const BACKGROUND: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+
+#[derive(Component, Clone, Copy)]
+pub enum PauseButton {
+    Resume,
+    Settings,
+    Back,
+    Quit,
+}
+
+// -- What a setting button does to the settings when clicked --
+#[derive(Component, Clone, Copy)]
+pub enum SettingButton {
+    Sensitivity(f32),
+    InvertX,
+    InvertY,
+    RenderDistance(i32),
+    Fov(f32),
+    Msaa,
+}
+
+// -- Text showing the current value of a setting --
+#[derive(Component, Clone, Copy)]
+pub enum SettingValue {
+    Sensitivity,
+    InvertX,
+    InvertY,
+    RenderDistance,
+    Fov,
+    Msaa,
+}
+
+// -- How a settings row changes its value --
+enum Control {
+    // -- A -/+ pair around the value
+    Steps(SettingButton, SettingButton),
+
+    // -- One button that shows the value
+    Toggle(SettingButton),
+}
+
+impl SettingValue {
+    fn text(&self, settings: &Settings) -> String {
+        let on_off = |value: bool| if value { "On" } else { "Off" }.to_string();
+
+        match self {
+            SettingValue::Sensitivity => format!("{:.0}", settings.controls.mouse_sensitivity),
+            SettingValue::InvertX => on_off(settings.controls.inverted_x),
+            SettingValue::InvertY => on_off(settings.controls.inverted_y),
+            SettingValue::RenderDistance => format!("{}", settings.graphics.render_distance),
+            SettingValue::Fov => format!("{:.0}", settings.camera.fov),
+            SettingValue::Msaa => format!("{}x", settings.graphics.msaa_samples),
+        }
+    }
+}
+
+pub fn spawn(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    spawn_pause_page(&mut commands, asset_server.load(FONT));
+}
+
+fn spawn_pause_page(commands: &mut Commands, font: Handle<Font>) {
+    spawn_screen(commands, BACKGROUND)
+        .with_children(|screen| {
+            screen.spawn_bundle(text("Paused", font.clone(), 48.0));
+            button(screen, "Resume", font.clone(), PauseButton::Resume);
+            button(screen, "Settings", font.clone(), PauseButton::Settings);
+            button(screen, "Quit", font, PauseButton::Quit);
+        });
+}
+
+fn spawn_settings_page(commands: &mut Commands, font: Handle<Font>, settings: &Settings) {
+    // -- Label, then either -/value/+ or a single toggle showing the value
+    let row = |screen: &mut ChildBuilder, label: &str, value: SettingValue, control: Control| {
+        screen.spawn_bundle(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::rgba(0.0, 0.0, 0.0, 0.0).into(),
+            ..default()
+        })
+        .with_children(|row| {
+            row.spawn_bundle(text(label, font.clone(), 24.0).with_style(Style {
+                size: Size::new(Val::Px(220.0), Val::Auto),
+                ..default()
+            }));
+
+            match control {
+                Control::Steps(down, up) => {
+                    small_button(row, "-", font.clone(), down);
+                    row.spawn_bundle(text(value.text(settings), font.clone(), 24.0).with_style(Style {
+                        size: Size::new(Val::Px(80.0), Val::Auto),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    }))
+                    .insert(value);
+                    small_button(row, "+", font.clone(), up);
+                }
+                Control::Toggle(toggle) => {
+                    // -- The label of the button is the value
+                    row.spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(176.0), Val::Px(48.0)),
+                            margin: UiRect::all(Val::Px(6.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        color: BUTTON_COLOR.into(),
+                        ..default()
+                    })
+                    .insert(toggle)
+                    .with_children(|button| {
+                        button.spawn_bundle(text(value.text(settings), font.clone(), 24.0)).insert(value);
+                    });
+                }
+            }
+        });
+    };
+
+    spawn_screen(commands, BACKGROUND)
+        .with_children(|screen| {
+            screen.spawn_bundle(text("Settings", font.clone(), 48.0));
+
+            row(screen, "Mouse sensitivity", SettingValue::Sensitivity,
+                Control::Steps(SettingButton::Sensitivity(-2.0), SettingButton::Sensitivity(2.0)));
+            row(screen, "Invert X", SettingValue::InvertX, Control::Toggle(SettingButton::InvertX));
+            row(screen, "Invert Y", SettingValue::InvertY, Control::Toggle(SettingButton::InvertY));
+            row(screen, "Render distance", SettingValue::RenderDistance,
+                Control::Steps(SettingButton::RenderDistance(-1), SettingButton::RenderDistance(1)));
+            row(screen, "Field of view", SettingValue::Fov,
+                Control::Steps(SettingButton::Fov(-5.0), SettingButton::Fov(5.0)));
+            row(screen, "Anti-aliasing", SettingValue::Msaa, Control::Toggle(SettingButton::Msaa));
+
+            button(screen, "Back", font.clone(), PauseButton::Back);
+        });
+}
+
+pub fn buttons(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut state: ResMut<State<GameState>>,
+    mut exit: EventWriter<AppExit>,
+    settings: Res<Settings>,
+    buttons: Query<(&Interaction, &PauseButton), Changed<Interaction>>,
+    screens: Query<Entity, With<Screen>>,
+) {
+    // -- Swap the whole screen for another page
+    let despawn_page = |commands: &mut Commands| {
+        for entity in screens.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+    };
+
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Clicked { continue; }
+
+        match button {
+            PauseButton::Resume => { let _ = state.pop(); },
+            PauseButton::Settings => {
+                despawn_page(&mut commands);
+                spawn_settings_page(&mut commands, asset_server.load(FONT), &settings);
+            },
+            PauseButton::Back => {
+                despawn_page(&mut commands);
+                spawn_pause_page(&mut commands, asset_server.load(FONT));
+            },
+            PauseButton::Quit => exit.send(AppExit),
+        }
+    }
+}
+
+// -- Changing the settings resource applies them live, see settings::apply_settings --
+pub fn setting_buttons(
+    mut settings: ResMut<Settings>,
+    buttons: Query<(&Interaction, &SettingButton), Changed<Interaction>>,
+) {
+    for (interaction, button) in buttons.iter() {
+        if *interaction != Interaction::Clicked { continue; }
+
+        match *button {
+            SettingButton::Sensitivity(step) => {
+                let sensitivity = &mut settings.controls.mouse_sensitivity;
+                *sensitivity = (*sensitivity + step).clamp(2.0, 100.0);
+            },
+            SettingButton::InvertX => settings.controls.inverted_x = !settings.controls.inverted_x,
+            SettingButton::InvertY => settings.controls.inverted_y = !settings.controls.inverted_y,
+            SettingButton::RenderDistance(step) => {
+                let distance = &mut settings.graphics.render_distance;
+                *distance = (*distance + step).clamp(2, 16);
+            },
+            SettingButton::Fov(step) => {
+                let fov = &mut settings.camera.fov;
+                *fov = (*fov + step).clamp(30.0, 120.0);
+            },
+            // -- Bevy only supports 1 or 4 samples
+            SettingButton::Msaa => {
+                let samples = &mut settings.graphics.msaa_samples;
+                *samples = if *samples > 1 { 1 } else { 4 };
+            },
+        }
+    }
+}
+
+pub fn setting_values(
+    settings: Res<Settings>,
+    mut texts: Query<(&mut Text, &SettingValue)>,
+) {
+    if !settings.is_changed() { return; }
+
+    for (mut text, value) in texts.iter_mut() {
+        text.sections[0].value = value.text(&settings);
+    }
+}